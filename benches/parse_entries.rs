@@ -0,0 +1,51 @@
+#[macro_use]
+extern crate criterion;
+extern crate squirrel;
+
+use criterion::{BenchmarkId, Criterion, Throughput};
+use squirrel::{ReleaseEntry, ReleaseFile};
+use std::io::Cursor;
+
+const RELEASES: &str = include_str!("fixtures/RELEASES");
+
+fn first_n_entries(n: usize) -> String {
+  RELEASES.lines().take(n + 1).collect::<Vec<_>>().join("\n")
+}
+
+fn parse_entries(c: &mut Criterion) {
+  let mut group = c.benchmark_group("parse_entries");
+
+  for n in &[10, 100, 1000, 10000] {
+    let content = first_n_entries(*n);
+
+    group.throughput(Throughput::Elements(*n as u64));
+    group.bench_with_input(BenchmarkId::from_parameter(n), &content, |b, content| {
+      b.iter(|| ReleaseEntry::parse_entries(content).unwrap())
+    });
+  }
+
+  group.finish();
+}
+
+fn from_reader(c: &mut Criterion) {
+  let mut group = c.benchmark_group("from_reader");
+
+  for n in &[10, 100, 1000, 10000] {
+    let content = first_n_entries(*n);
+
+    group.throughput(Throughput::Elements(*n as u64));
+    group.bench_with_input(BenchmarkId::from_parameter(n), &content, |b, content| {
+      b.iter(|| ReleaseFile::from_reader(Cursor::new(content.as_bytes())).unwrap())
+    });
+  }
+
+  group.finish();
+}
+
+fn parse(c: &mut Criterion) {
+  let line = RELEASES.lines().nth(1).unwrap();
+  c.bench_function("parse", |b| b.iter(|| ReleaseEntry::parse(line).unwrap()));
+}
+
+criterion_group!(benches, parse_entries, from_reader, parse);
+criterion_main!(benches);
@@ -0,0 +1,12 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate squirrel;
+
+use squirrel::ReleaseEntry;
+
+fuzz_target!(|data: &[u8]| {
+  if let Ok(content) = std::str::from_utf8(data) {
+    let _ = ReleaseEntry::parse_entries(content);
+  }
+});
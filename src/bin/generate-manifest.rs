@@ -0,0 +1,71 @@
+//! Writes a RELEASES file for the packages in a directory to stdout
+//!
+//! Usage: generate-manifest <dir> <version> [--channel <name>] [--percentage <n>]
+
+use semver::Version;
+use squirrel::{ReleaseEntry, ReleaseFile};
+use std::env;
+use std::error::Error;
+use std::path::PathBuf;
+use std::process;
+
+const USAGE: &str = "usage: generate-manifest <dir> <version> [--channel <name>] [--percentage <n>]";
+
+struct Args {
+  dir: PathBuf,
+  version: Version,
+  channel: Option<String>,
+  percentage: Option<i32>,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Args, Box<dyn Error>> {
+  let mut positional = Vec::new();
+  let mut channel = None;
+  let mut percentage = None;
+
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--channel" => channel = Some(args.next().ok_or("--channel needs a value")?),
+      "--percentage" => percentage = Some(args.next().ok_or("--percentage needs a value")?.parse::<i32>()?),
+      x if x.starts_with("--") => return Err(format!("unknown option '{}'", x).into()),
+      _ => positional.push(arg),
+    }
+  }
+
+  if positional.len() != 2 {
+    return Err(USAGE.into());
+  }
+
+  let version = Version::parse(positional[1].trim_start_matches(['v', 'V']))?;
+  Ok(Args { dir: PathBuf::from(&positional[0]), version, channel, percentage })
+}
+
+/// Whether the package is for `channel`, going by a `-<channel>-` style
+/// segment in its filename, e.g. `myapp-1.2.3-beta-full.nupkg`
+fn is_in_channel(entry: &ReleaseEntry, channel: &str) -> bool {
+  entry.filename()
+    .split(['-', '_', '.'])
+    .any(|x| x.eq_ignore_ascii_case(channel))
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+  let args = parse_args(env::args().skip(1))?;
+
+  let mut file = ReleaseFile::generate_from_dir(&args.dir, &args.version)?;
+  if let Some(ref channel) = args.channel {
+    file = file.into_iter().filter(|x| is_in_channel(x, channel)).collect();
+  }
+  if let Some(percentage) = args.percentage {
+    file.set_percentage_for_version(&args.version, percentage)?;
+  }
+
+  print!("{}", file);
+  Ok(())
+}
+
+fn main() {
+  if let Err(err) = run() {
+    eprintln!("generate-manifest: {}", err);
+    process::exit(1);
+  }
+}
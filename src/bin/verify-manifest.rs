@@ -0,0 +1,51 @@
+//! Checks downloaded packages against the RELEASES file they came from,
+//! printing one status line per entry
+//!
+//! Usage: verify-manifest <RELEASES> <dir>
+
+use squirrel::{ReleaseEntry, ReleaseEntryError, ReleaseFile};
+use std::env;
+use std::error::Error;
+use std::io;
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "usage: verify-manifest <RELEASES> <dir>";
+
+/// Returns whether every entry verified
+fn run() -> Result<bool, Box<dyn Error>> {
+  let args = env::args().skip(1).collect::<Vec<_>>();
+  if args.len() != 2 {
+    return Err(USAGE.into());
+  }
+
+  let file = ReleaseFile::from_file(Path::new(&args[0]))?;
+  let dir = Path::new(&args[1]);
+
+  let mut all_ok = true;
+  for (entry, result) in ReleaseEntry::verify_dir(file.entries(), dir) {
+    let status = match result {
+      Ok(()) => "OK".to_owned(),
+      Err(ReleaseEntryError::Io(ref err)) if err.kind() == io::ErrorKind::NotFound => "MISSING".to_owned(),
+      Err(ReleaseEntryError::Sha256Mismatch) => "HASH_MISMATCH".to_owned(),
+      Err(ReleaseEntryError::LengthMismatch { .. }) => "SIZE_MISMATCH".to_owned(),
+      Err(err) => format!("ERROR ({})", err),
+    };
+
+    all_ok &= status == "OK";
+    println!("{} {}", status, entry.filename());
+  }
+
+  Ok(all_ok)
+}
+
+fn main() {
+  match run() {
+    Ok(true) => (),
+    Ok(false) => process::exit(1),
+    Err(err) => {
+      eprintln!("verify-manifest: {}", err);
+      process::exit(2);
+    }
+  }
+}
@@ -0,0 +1,107 @@
+//! Hashing helpers shared by the rest of the crate
+
+use crate::sha256_hash::{Sha256Hash, SHA256_BYTES};
+use sha2::{Digest, Sha256};
+use std::io::{self, Read, Write};
+
+/// Streams `reader` through SHA256 in 64 KiB chunks, so that large packages
+/// never have to be held in memory.
+pub fn sha256_of_reader<R: Read>(mut reader: R) -> Result<Sha256Hash, io::Error> {
+  let mut sha = Sha256::default();
+  let mut buf = vec![0; 64 * 1024];
+  loop {
+    let n = reader.read(&mut buf)?;
+    if n == 0 {
+      break;
+    }
+
+    sha.input(&buf[..n]);
+  }
+
+  Ok(finish(sha))
+}
+
+fn finish(sha: Sha256) -> Sha256Hash {
+  let mut ret = [0; SHA256_BYTES];
+  ret.copy_from_slice(&sha.result());
+  Sha256Hash::from(ret)
+}
+
+/// Hashes everything written through it on the way to `inner`, so that a
+/// download can be verified without reading the file back afterwards.
+pub struct VerifyingWriter<W: Write> {
+  inner: W,
+  hasher: Sha256,
+  bytes_written: u64,
+}
+
+impl<W: Write> VerifyingWriter<W> {
+  pub fn new(inner: W) -> VerifyingWriter<W> {
+    VerifyingWriter { inner, hasher: Sha256::default(), bytes_written: 0 }
+  }
+
+  /// Returns the inner writer along with the SHA256 and size of everything
+  /// written so far.
+  pub fn finalize(self) -> (W, Sha256Hash, u64) {
+    (self.inner, finish(self.hasher), self.bytes_written)
+  }
+}
+
+impl<W: Write> Write for VerifyingWriter<W> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    // Only hash what the inner writer actually took, partial writes get
+    // retried with the rest
+    let n = self.inner.write(buf)?;
+    self.hasher.input(&buf[..n]);
+    self.bytes_written += n as u64;
+    Ok(n)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.inner.flush()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{sha256_of_reader, VerifyingWriter};
+  use sha2::{Digest, Sha256};
+  use std::io::{self, Cursor, Write};
+
+  #[test]
+  fn hashes_across_chunks() {
+    let empty = sha256_of_reader(Cursor::new(Vec::new())).unwrap();
+    assert_eq!(empty.to_string(), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+
+    let big = vec![b'a'; 200 * 1024];
+    let mut sha = Sha256::default();
+    sha.input(&big);
+
+    let hash = sha256_of_reader(Cursor::new(&big)).unwrap();
+    assert_eq!(hash.as_ref(), &sha.result()[..]);
+  }
+
+  #[test]
+  fn verifying_writer_hashes_what_it_writes() {
+    let mut writer = VerifyingWriter::new(Vec::new());
+    writer.write_all(b"This is ").unwrap();
+    writer.write_all(b"a test").unwrap();
+    writer.flush().unwrap();
+
+    let (inner, hash, length) = writer.finalize();
+    assert_eq!(inner, b"This is a test");
+    assert_eq!(hash.to_string(), "c7be1ed902fb8dd4d48997c6452f5d7e509fbcdbe2808b16bcf4edce4c07d14e");
+    assert_eq!(length, 14);
+  }
+
+  #[test]
+  fn verifying_writer_matches_reader_hash() {
+    let data = vec![7; 100 * 1024];
+    let mut writer = VerifyingWriter::new(io::sink());
+    io::copy(&mut Cursor::new(&data), &mut writer).unwrap();
+
+    let (_, hash, length) = writer.finalize();
+    assert_eq!(hash, sha256_of_reader(Cursor::new(&data)).unwrap());
+    assert_eq!(length, data.len() as u64);
+  }
+}
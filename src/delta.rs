@@ -0,0 +1,125 @@
+use crate::release_entry::ReleaseEntry;
+use semver::Version;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+/// Rebuilds a full package from an older one plus a delta, e.g. with bsdiff
+/// or zstd. Plug an implementation into `ReleaseEntry::apply_delta_with`,
+/// which takes care of verifying the output.
+pub trait DeltaApplier {
+  fn apply(&self, base: &Path, delta: &Path, out: &Path) -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+/// Why a `DeltaChain` can't take `from_version` to `to_version`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainError {
+  Empty,
+  NotADelta { step: usize },
+  /// A step doesn't go past the version the previous step left off at
+  OutOfOrder { step: usize },
+  /// The last step ends at `found` rather than `to_version`
+  WrongTarget { found: Version },
+}
+
+impl fmt::Display for ChainError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      ChainError::Empty => write!(f, "delta chain has no steps"),
+      ChainError::NotADelta { step } => write!(f, "step {} of the delta chain is not a delta", step + 1),
+      ChainError::OutOfOrder { step } => write!(f, "step {} of the delta chain goes backwards", step + 1),
+      ChainError::WrongTarget { ref found } => write!(f, "delta chain ends at the wrong version, {}", found),
+    }
+  }
+}
+
+impl Error for ChainError {}
+
+/// Deltas to apply in order to get from one full version to another. Each
+/// step applies on top of the version the previous one produced, the first
+/// on top of `from_version`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeltaChain {
+  pub steps: Vec<ReleaseEntry>,
+  pub from_version: Version,
+  pub to_version: Version,
+}
+
+impl DeltaChain {
+  /// The size of every step. Steps of unknown size count as 0.
+  pub fn total_bytes(&self) -> u64 {
+    self.steps.iter().filter_map(|x| x.length).sum()
+  }
+
+  pub fn step_count(&self) -> usize {
+    self.steps.len()
+  }
+
+  /// Checks that every step is a delta going past the previous step's
+  /// version, and that the last one ends at `to_version`. Build metadata is
+  /// ignored.
+  pub fn validate(&self) -> Result<(), ChainError> {
+    let mut at = self.from_version.clone();
+    for (i, step) in self.steps.iter().enumerate() {
+      if !step.is_delta() {
+        return Err(ChainError::NotADelta { step: i });
+      }
+      if !step.is_newer_than(&at) {
+        return Err(ChainError::OutOfOrder { step: i });
+      }
+
+      at = step.version_core();
+    }
+
+    match self.steps.last() {
+      None => Err(ChainError::Empty),
+      Some(x) if !x.is_same_version(&self.to_version) =>
+        Err(ChainError::WrongTarget { found: x.version.clone() }),
+      Some(_) => Ok(())
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use semver::Version;
+  use super::{ChainError, DeltaChain};
+  use crate::release_entry::ReleaseEntry;
+
+  fn chain(steps: &str, from: &str, to: &str) -> DeltaChain {
+    DeltaChain {
+      steps: ReleaseEntry::parse_entries(steps).unwrap(),
+      from_version: Version::parse(from).unwrap(),
+      to_version: Version::parse(to).unwrap(),
+    }
+  }
+
+  const STEPS: &str = "
+b4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-1.1.0-delta.7z 1.1.0 555 delta
+c4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-1.2.0-delta.7z 1.2.0 666 delta";
+
+  #[test]
+  fn valid_chain_sums_steps() {
+    let chain = chain(STEPS, "1.0.0", "1.2.0");
+
+    chain.validate().unwrap();
+    assert_eq!(chain.step_count(), 2);
+    assert_eq!(chain.total_bytes(), 555 + 666);
+  }
+
+  #[test]
+  fn validate_rejects_broken_chains() {
+    assert_eq!(chain("", "1.0.0", "1.2.0").validate(), Err(ChainError::Empty));
+
+    assert_eq!(chain(STEPS, "1.1.0", "1.2.0").validate(), Err(ChainError::OutOfOrder { step: 0 }));
+
+    match chain(STEPS, "1.0.0", "1.3.0").validate() {
+      Err(ChainError::WrongTarget { ref found }) if found.to_string() == "1.2.0" => (),
+      x => panic!("Expected WrongTarget, got {:?}", x)
+    }
+
+    let err = chain(&STEPS.replace("666 delta", "666 full"), "1.0.0", "1.2.0").validate().unwrap_err();
+    assert_eq!(err, ChainError::NotADelta { step: 1 });
+    assert_eq!(err.to_string(), "step 2 of the delta chain is not a delta");
+  }
+}
@@ -0,0 +1,252 @@
+use crate::release_entry::ReleaseEntry;
+use std::error::{Error};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Fetches a package to disk, e.g. through a corporate proxy, with client
+/// certificates or throttled. Plug an implementation into
+/// `ReleaseEntry::download_with`, which takes care of verifying the file.
+pub trait Downloader {
+  fn download(&self, url: &str, dest: &Path, expected: &ReleaseEntry) -> Result<(), DownloadError>;
+
+  /// Like `download`, calling `progress` with the bytes downloaded so far
+  /// and the total expected, from `Content-Length` or else the entry's
+  /// length (0 if neither is known). By default it's only called once the
+  /// download is done; stream-based implementations should call it as data
+  /// arrives.
+  fn download_with_progress(&self, url: &str, dest: &Path, expected: &ReleaseEntry, progress: &dyn Fn(u64, u64)) -> Result<(), DownloadError> {
+    self.download(url, dest, expected)?;
+    let length = fs::metadata(dest)?.len();
+    progress(length, expected.length.unwrap_or(length));
+    Ok(())
+  }
+
+  /// Appends everything from byte `offset` on to the partial download at
+  /// `dest`, without verifying it, as only the whole file can be checked.
+  /// By default this can't resume and downloads the whole file again.
+  fn download_from(&self, url: &str, dest: &Path, offset: u64, expected: &ReleaseEntry) -> Result<(), DownloadError> {
+    debug!("Can't resume {} from byte {}, downloading all of it", url, offset);
+    self.download(url, dest, expected)
+  }
+
+  /// Streams the package into `writer`, e.g. a `VerifyingWriter` that checks
+  /// it as it's written. By default it's downloaded to `scratch` with
+  /// `download`, then copied into `writer` and removed; implementations that
+  /// can stream should write straight to `writer` instead.
+  fn download_to_writer(&self, url: &str, writer: &mut dyn Write, scratch: &Path, expected: &ReleaseEntry) -> Result<(), DownloadError> {
+    let result = self.download(url, scratch, expected).and_then(|_| {
+      io::copy(&mut File::open(scratch)?, writer)?;
+      Ok(())
+    });
+
+    let _ = fs::remove_file(scratch);
+    result
+  }
+}
+
+/// Picks up where an interrupted download left off, if something is already
+/// at the destination, then checks the SHA256 of the whole file. A file that
+/// fails the check is removed, so that the next attempt starts over.
+#[derive(Debug, Clone)]
+pub struct ResumeableDownloader<D: Downloader> {
+  inner: D,
+}
+
+impl<D: Downloader> ResumeableDownloader<D> {
+  pub fn new(inner: D) -> ResumeableDownloader<D> {
+    ResumeableDownloader { inner }
+  }
+
+  pub fn into_inner(self) -> D {
+    self.inner
+  }
+}
+
+impl<D: Downloader> Downloader for ResumeableDownloader<D> {
+  fn download(&self, url: &str, dest: &Path, expected: &ReleaseEntry) -> Result<(), DownloadError> {
+    let partial = fs::metadata(dest).map(|x| x.len()).unwrap_or(0);
+    let result = match expected.length {
+      // Already all there, e.g. the last attempt failed after the download
+      Some(length) if partial == length => Ok(()),
+      // Bigger than it should be, so it isn't a prefix of this package
+      Some(length) if partial > length => self.inner.download(url, dest, expected),
+      _ if partial > 0 => self.inner.download_from(url, dest, partial, expected),
+      _ => self.inner.download(url, dest, expected)
+    };
+
+    let result = result.and_then(|_| expected.verify_download(dest));
+    if let Err(DownloadError::HashMismatch) = result {
+      let _ = fs::remove_file(dest);
+    }
+
+    result
+  }
+}
+
+/// Everything that can go wrong while downloading a package
+#[derive(Debug)]
+pub enum DownloadError {
+  Network(Box<dyn Error + Send + Sync>),
+  /// The downloaded file doesn't have the SHA256 or size the entry lists
+  HashMismatch,
+  DiskFull,
+  PermissionDenied,
+  Io(io::Error),
+}
+
+impl fmt::Display for DownloadError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      DownloadError::Network(ref err) => write!(f, "download failed: {}", err),
+      DownloadError::HashMismatch => write!(f, "downloaded file does not match its RELEASES entry"),
+      DownloadError::DiskFull => write!(f, "not enough disk space for the download"),
+      DownloadError::PermissionDenied => write!(f, "not allowed to write the download"),
+      DownloadError::Io(ref err) => write!(f, "{}", err),
+    }
+  }
+}
+
+impl Error for DownloadError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match *self {
+      DownloadError::Network(ref err) => Some(&**err),
+      DownloadError::Io(ref err) => Some(err),
+      _ => None
+    }
+  }
+}
+
+impl From<io::Error> for DownloadError {
+  fn from(err: io::Error) -> DownloadError {
+    match err.kind() {
+      io::ErrorKind::StorageFull => DownloadError::DiskFull,
+      io::ErrorKind::PermissionDenied => DownloadError::PermissionDenied,
+      _ => DownloadError::Io(err)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{DownloadError, Downloader, ResumeableDownloader};
+  use crate::crypto::sha256_of_reader;
+  use crate::release_entry::ReleaseEntry;
+  use std::cell::{Cell, RefCell};
+  use std::fs::{self, OpenOptions};
+  use std::io::{self, Write};
+  use std::path::Path;
+
+  struct WritingDownloader;
+
+  impl Downloader for WritingDownloader {
+    fn download(&self, _url: &str, dest: &Path, _expected: &ReleaseEntry) -> Result<(), DownloadError> {
+      fs::write(dest, "This is a test")?;
+      Ok(())
+    }
+  }
+
+  /// Serves `contents`, recording the offset of each resumed download
+  struct RangeDownloader {
+    contents: &'static [u8],
+    resumed_from: Cell<Option<u64>>,
+  }
+
+  impl Downloader for RangeDownloader {
+    fn download(&self, _url: &str, dest: &Path, _expected: &ReleaseEntry) -> Result<(), DownloadError> {
+      fs::write(dest, self.contents)?;
+      Ok(())
+    }
+
+    fn download_from(&self, _url: &str, dest: &Path, offset: u64, _expected: &ReleaseEntry) -> Result<(), DownloadError> {
+      self.resumed_from.set(Some(offset));
+      OpenOptions::new().append(true).open(dest)?.write_all(&self.contents[offset as usize..])?;
+      Ok(())
+    }
+  }
+
+  fn resumeable(contents: &'static [u8]) -> ResumeableDownloader<RangeDownloader> {
+    ResumeableDownloader::new(RangeDownloader { contents, resumed_from: Cell::new(None) })
+  }
+
+  fn entry_for(contents: &[u8]) -> ReleaseEntry {
+    ReleaseEntry { sha256: sha256_of_reader(contents).unwrap(), length: Some(contents.len() as u64), ..ReleaseEntry::default() }
+  }
+
+  #[test]
+  fn resumeable_downloader_appends_to_partial_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("myproject.7z");
+    fs::write(&dest, "This is").unwrap();
+
+    let downloader = resumeable(b"This is a test");
+    downloader.download("https://example.com/myproject.7z", &dest, &entry_for(b"This is a test")).unwrap();
+    assert_eq!(downloader.into_inner().resumed_from.get(), Some(7));
+    assert_eq!(fs::read(&dest).unwrap(), b"This is a test");
+  }
+
+  #[test]
+  fn resumeable_downloader_starts_fresh_without_partial_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("myproject.7z");
+
+    let downloader = resumeable(b"This is a test");
+    downloader.download("https://example.com/myproject.7z", &dest, &entry_for(b"This is a test")).unwrap();
+    assert_eq!(downloader.into_inner().resumed_from.get(), None);
+    assert_eq!(fs::read(&dest).unwrap(), b"This is a test");
+  }
+
+  #[test]
+  fn resumeable_downloader_removes_corrupt_result() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("myproject.7z");
+    fs::write(&dest, "That is").unwrap();
+
+    match resumeable(b"This is a test").download("https://example.com/myproject.7z", &dest, &entry_for(b"This is a test")) {
+      Err(DownloadError::HashMismatch) => (),
+      x => panic!("Expected HashMismatch, got {:?}", x)
+    }
+    assert!(!dest.exists());
+  }
+
+  #[test]
+  fn default_download_to_writer_goes_through_scratch_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let scratch = dir.path().join("myproject.7z.part");
+
+    let mut written = Vec::new();
+    WritingDownloader.download_to_writer("https://example.com/myproject.7z", &mut written, &scratch, &ReleaseEntry::default()).unwrap();
+    assert_eq!(written, b"This is a test");
+    assert!(!scratch.exists());
+  }
+
+  #[test]
+  fn default_progress_reports_once_done() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("myproject.7z");
+    let calls = RefCell::new(Vec::new());
+
+    let entry = ReleaseEntry { length: Some(14), ..ReleaseEntry::default() };
+    WritingDownloader.download_with_progress("https://example.com/myproject.7z", &dest, &entry, &|done, total| calls.borrow_mut().push((done, total))).unwrap();
+    assert_eq!(calls.into_inner(), vec![(14, 14)]);
+  }
+
+  #[test]
+  fn io_errors_map_to_specific_variants() {
+    match DownloadError::from(io::Error::from(io::ErrorKind::StorageFull)) {
+      DownloadError::DiskFull => (),
+      x => panic!("Expected DiskFull, got {:?}", x)
+    }
+
+    match DownloadError::from(io::Error::from(io::ErrorKind::PermissionDenied)) {
+      DownloadError::PermissionDenied => (),
+      x => panic!("Expected PermissionDenied, got {:?}", x)
+    }
+
+    match DownloadError::from(io::Error::from(io::ErrorKind::NotFound)) {
+      DownloadError::Io(ref err) if err.kind() == io::ErrorKind::NotFound => (),
+      x => panic!("Expected Io, got {:?}", x)
+    }
+  }
+}
@@ -0,0 +1,151 @@
+use semver::SemVerError;
+#[cfg(feature = "sign")]
+use crate::sign::SignatureError;
+use std::error::{Error};
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::str::Utf8Error;
+
+/// Everything that can go wrong while reading a RELEASES entry
+#[derive(Debug)]
+pub enum ReleaseEntryError {
+  MalformedSha256 { reason: &'static str },
+  InvalidPackageType { value: String },
+  InvalidUrl(url::ParseError),
+  UrlContainsCredentials,
+  HostNotAllowed { host: String },
+  MalformedSignature,
+  InvalidFilename(Utf8Error),
+  InvalidVersion(SemVerError),
+  VersionLooksLikeSize { value: String },
+  InvalidLength { value: String },
+  InvalidPercentage { value: String },
+  WrongColumnCount { found: usize },
+  LineTooLong { length: usize, max: usize },
+  InvalidExpiry(chrono::ParseError),
+  AtLine { line: usize, error: Box<ReleaseEntryError> },
+  UnknownDirective { value: String },
+  DecompressedTooLarge { max: u64 },
+  InFile { path: PathBuf, error: Box<ReleaseEntryError> },
+  LengthMismatch { expected: u64, actual: u64 },
+  Sha256Mismatch,
+  Io(io::Error),
+  MissingField(&'static str),
+  UnsafeFilename { name: String },
+  DeltaFailed(Box<dyn Error + Send + Sync>),
+  #[cfg(feature = "sign")]
+  InvalidSignature(SignatureError),
+}
+
+impl fmt::Display for ReleaseEntryError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      ReleaseEntryError::MalformedSha256 { reason } => write!(f, "SHA256 is malformed: {}", reason),
+      ReleaseEntryError::InvalidPackageType { ref value } =>
+        write!(f, "package type must be 'delta', 'full' or 'staging', found '{}'", value),
+      ReleaseEntryError::InvalidUrl(ref err) => write!(f, "invalid URL: {}", err),
+      ReleaseEntryError::UrlContainsCredentials => write!(f, "URL must not contain a username or password"),
+      ReleaseEntryError::HostNotAllowed { ref host } => write!(f, "URL host '{}' is not allowed", host),
+      ReleaseEntryError::MalformedSignature => write!(f, "signature is not valid base64"),
+      ReleaseEntryError::InvalidFilename(ref err) => write!(f, "invalid filename: {}", err),
+      ReleaseEntryError::InvalidVersion(ref err) => write!(f, "invalid version: {}", err),
+      ReleaseEntryError::VersionLooksLikeSize { ref value } =>
+        write!(f, "column 3 '{}' looks like a size, not a version; is this a legacy 4-column line?", value),
+      ReleaseEntryError::InvalidLength { ref value } => write!(f, "invalid size '{}'", value),
+      ReleaseEntryError::InvalidPercentage { ref value } =>
+        write!(f, "invalid percentage '{}', must be between 0 and 100 inclusive", value),
+      ReleaseEntryError::WrongColumnCount { found } =>
+        write!(f, "expected 5 or 6 columns, found {}", found),
+      ReleaseEntryError::LineTooLong { length, max } =>
+        write!(f, "line is {} bytes long, the maximum is {}", length, max),
+      ReleaseEntryError::InvalidExpiry(ref err) => write!(f, "invalid expiry timestamp: {}", err),
+      ReleaseEntryError::AtLine { line, ref error } => write!(f, "line {}: {}", line, error),
+      ReleaseEntryError::UnknownDirective { ref value } => write!(f, "unknown RELEASES directive '{}'", value),
+      ReleaseEntryError::DecompressedTooLarge { max } =>
+        write!(f, "decompressed RELEASES file is larger than {} bytes", max),
+      ReleaseEntryError::InFile { ref path, ref error } => match **error {
+        ReleaseEntryError::AtLine { line, ref error } =>
+          write!(f, "failed to parse {} at line {}: {}", path.display(), line, error),
+        ref error => write!(f, "failed to parse {}: {}", path.display(), error),
+      },
+      ReleaseEntryError::LengthMismatch { expected, actual } =>
+        write!(f, "expected a file of {} bytes, found {} bytes", expected, actual),
+      ReleaseEntryError::Sha256Mismatch => write!(f, "file does not match the expected SHA256"),
+      ReleaseEntryError::Io(ref err) => write!(f, "{}", err),
+      ReleaseEntryError::MissingField(field) => write!(f, "no value was given for '{}'", field),
+      ReleaseEntryError::UnsafeFilename { ref name } =>
+        write!(f, "filename '{}' points outside the package directory", name),
+      ReleaseEntryError::DeltaFailed(ref err) => write!(f, "failed to apply delta: {}", err),
+      #[cfg(feature = "sign")]
+      ReleaseEntryError::InvalidSignature(ref err) => write!(f, "{}", err),
+    }
+  }
+}
+
+impl Error for ReleaseEntryError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match *self {
+      ReleaseEntryError::InvalidUrl(ref err) => Some(err),
+      ReleaseEntryError::InvalidFilename(ref err) => Some(err),
+      ReleaseEntryError::InvalidVersion(ref err) => Some(err),
+      ReleaseEntryError::InvalidExpiry(ref err) => Some(err),
+      ReleaseEntryError::Io(ref err) => Some(err),
+      ReleaseEntryError::AtLine { ref error, .. } => Some(&**error),
+      ReleaseEntryError::InFile { ref error, .. } => Some(&**error),
+      ReleaseEntryError::DeltaFailed(ref err) => Some(&**err),
+      #[cfg(feature = "sign")]
+      ReleaseEntryError::InvalidSignature(ref err) => Some(err),
+      _ => None
+    }
+  }
+}
+
+impl From<url::ParseError> for ReleaseEntryError {
+  fn from(err: url::ParseError) -> ReleaseEntryError {
+    ReleaseEntryError::InvalidUrl(err)
+  }
+}
+
+impl From<Utf8Error> for ReleaseEntryError {
+  fn from(err: Utf8Error) -> ReleaseEntryError {
+    ReleaseEntryError::InvalidFilename(err)
+  }
+}
+
+impl From<io::Error> for ReleaseEntryError {
+  fn from(err: io::Error) -> ReleaseEntryError {
+    ReleaseEntryError::Io(err)
+  }
+}
+
+#[cfg(feature = "sign")]
+impl From<SignatureError> for ReleaseEntryError {
+  fn from(err: SignatureError) -> ReleaseEntryError {
+    ReleaseEntryError::InvalidSignature(err)
+  }
+}
+
+impl From<SemVerError> for ReleaseEntryError {
+  fn from(err: SemVerError) -> ReleaseEntryError {
+    ReleaseEntryError::InvalidVersion(err)
+  }
+}
+
+/// Returned when adding an entry that conflicts with one already in a RELEASES file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateError {
+  SameSha256,
+  SameNameAndVersion,
+}
+
+impl fmt::Display for DuplicateError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      DuplicateError::SameSha256 => write!(f, "an entry with the same SHA256 already exists"),
+      DuplicateError::SameNameAndVersion => write!(f, "an entry with the same name and version already exists"),
+    }
+  }
+}
+
+impl Error for DuplicateError {}
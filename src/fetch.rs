@@ -0,0 +1,470 @@
+use crate::crypto::VerifyingWriter;
+use crate::download::{DownloadError, Downloader};
+use crate::error::ReleaseEntryError;
+use crate::release_entry::ReleaseEntry;
+use std::error::{Error};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/// Everything that can go wrong while downloading a RELEASES file
+#[derive(Debug)]
+pub enum FetchError {
+  Http(Box<ureq::Error>),
+  UnexpectedStatus(u16),
+  /// A resumed download didn't start at the byte that was asked for
+  UnexpectedRange(u64),
+  Io(io::Error),
+  InvalidManifest(ReleaseEntryError),
+}
+
+impl fmt::Display for FetchError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      FetchError::Http(ref err) => write!(f, "request failed: {}", err),
+      FetchError::UnexpectedStatus(status) => write!(f, "server responded with status {}", status),
+      FetchError::UnexpectedRange(offset) => write!(f, "server didn't resume the download from byte {}", offset),
+      FetchError::Io(ref err) => write!(f, "{}", err),
+      FetchError::InvalidManifest(ref err) => write!(f, "invalid RELEASES file: {}", err),
+    }
+  }
+}
+
+impl Error for FetchError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match *self {
+      FetchError::Http(ref err) => Some(&**err),
+      FetchError::Io(ref err) => Some(err),
+      FetchError::InvalidManifest(ref err) => Some(err),
+      _ => None
+    }
+  }
+}
+
+impl From<ureq::Error> for FetchError {
+  fn from(err: ureq::Error) -> FetchError {
+    match err {
+      ureq::Error::Status(status, _) => FetchError::UnexpectedStatus(status),
+      err => FetchError::Http(Box::new(err)),
+    }
+  }
+}
+
+impl From<io::Error> for FetchError {
+  fn from(err: io::Error) -> FetchError {
+    FetchError::Io(err)
+  }
+}
+
+impl From<ReleaseEntryError> for FetchError {
+  fn from(err: ReleaseEntryError) -> FetchError {
+    FetchError::InvalidManifest(err)
+  }
+}
+
+/// GETs `url`, sending `If-None-Match` when an `etag` is given. Returns
+/// `None` on a 304, otherwise the body along with the new ETag if any.
+pub(crate) fn get_manifest(url: &str, etag: Option<&str>, timeout: Duration) -> Result<Option<(String, Option<String>)>, FetchError> {
+  let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+  let mut request = agent.get(url);
+  if let Some(etag) = etag {
+    request = request.set("If-None-Match", etag);
+  }
+
+  let response = request.call()?;
+  match response.status() {
+    304 => {
+      debug!("{} is unchanged", url);
+      Ok(None)
+    },
+    200 => {
+      let etag = response.header("ETag").map(|x| x.to_owned());
+      Ok(Some((response.into_string()?, etag)))
+    },
+    status => Err(FetchError::UnexpectedStatus(status))
+  }
+}
+
+/// How long `UreqDownloader` waits before its first retry. Each retry after
+/// that waits twice as long as the one before.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// A `Downloader` that streams packages straight to disk with `ureq`,
+/// retrying server errors with exponential backoff and checking the SHA256
+/// as the file is written.
+#[derive(Debug, Clone)]
+pub struct UreqDownloader {
+  max_retries: u32,
+  timeout: Duration,
+  auth_header: Option<String>,
+}
+
+impl Default for UreqDownloader {
+  fn default() -> UreqDownloader {
+    UreqDownloader {
+      max_retries: 3,
+      timeout: Duration::from_secs(30),
+      auth_header: None,
+    }
+  }
+}
+
+impl UreqDownloader {
+  pub fn new() -> UreqDownloader {
+    UreqDownloader::default()
+  }
+
+  /// How many times to retry after a 5xx response before giving up
+  pub fn with_max_retries(mut self, max_retries: u32) -> UreqDownloader {
+    self.max_retries = max_retries;
+    self
+  }
+
+  pub fn with_timeout(mut self, d: Duration) -> UreqDownloader {
+    self.timeout = d;
+    self
+  }
+
+  /// Sends `Authorization: Bearer <t>` with every request
+  pub fn with_bearer_token(mut self, t: String) -> UreqDownloader {
+    self.auth_header = Some(format!("Bearer {}", t));
+    self
+  }
+
+  /// GETs `url`, asking for everything from byte `offset` on if given
+  fn get(&self, url: &str, offset: Option<u64>) -> Result<ureq::Response, DownloadError> {
+    let agent = ureq::AgentBuilder::new().timeout(self.timeout).build();
+    let mut attempt = 0;
+    loop {
+      let mut request = agent.get(url);
+      if let Some(ref header) = self.auth_header {
+        request = request.set("Authorization", header);
+      }
+      if let Some(offset) = offset {
+        request = request.set("Range", &format!("bytes={}-", offset));
+      }
+
+      match request.call() {
+        Ok(response) => return Ok(response),
+        // Nothing past `offset`, which `download_from` deals with
+        Err(ureq::Error::Status(416, response)) if offset.is_some() => return Ok(response),
+        Err(ureq::Error::Status(status, _)) if status >= 500 && attempt < self.max_retries => {
+          let delay = RETRY_DELAY * 2u32.pow(attempt);
+          warn!("{} responded with status {}, retrying in {:?}", url, status, delay);
+          thread::sleep(delay);
+          attempt += 1;
+        },
+        Err(err) => return Err(DownloadError::Network(Box::new(FetchError::from(err))))
+      }
+    }
+  }
+}
+
+/// Reports how much has been read through it to a progress callback
+struct ProgressReader<'a, R: Read> {
+  inner: R,
+  read: u64,
+  total: u64,
+  progress: &'a dyn Fn(u64, u64),
+}
+
+impl<'a, R: Read> Read for ProgressReader<'a, R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let n = self.inner.read(buf)?;
+    self.read += n as u64;
+    (self.progress)(self.read, self.total);
+    Ok(n)
+  }
+}
+
+impl Downloader for UreqDownloader {
+  fn download(&self, url: &str, dest: &Path, expected: &ReleaseEntry) -> Result<(), DownloadError> {
+    self.download_with_progress(url, dest, expected, &|_, _| ())
+  }
+
+  fn download_with_progress(&self, url: &str, dest: &Path, expected: &ReleaseEntry, progress: &dyn Fn(u64, u64)) -> Result<(), DownloadError> {
+    let response = self.get(url, None)?;
+    let total = response.header("Content-Length")
+      .and_then(|x| x.parse::<u64>().ok())
+      .or(expected.length)
+      .unwrap_or(0);
+
+    let mut writer = VerifyingWriter::new(File::create(dest)?);
+    let mut reader = ProgressReader { inner: response.into_reader(), read: 0, total, progress };
+    io::copy(&mut reader, &mut writer)?;
+    let (file, sha256, length) = writer.finalize();
+    file.sync_all()?;
+
+    if !expected.matches_written(&sha256, length) {
+      let _ = fs::remove_file(dest);
+      return Err(DownloadError::HashMismatch);
+    }
+
+    Ok(())
+  }
+
+  fn download_to_writer(&self, url: &str, writer: &mut dyn Write, _scratch: &Path, _expected: &ReleaseEntry) -> Result<(), DownloadError> {
+    io::copy(&mut self.get(url, None)?.into_reader(), writer)?;
+    Ok(())
+  }
+
+  fn download_from(&self, url: &str, dest: &Path, offset: u64, expected: &ReleaseEntry) -> Result<(), DownloadError> {
+    let response = self.get(url, Some(offset))?;
+
+    // The file is either already complete or longer than this package, and
+    // only the hash can tell which
+    if response.status() == 416 {
+      if expected.verify_download(dest).is_ok() {
+        return Ok(());
+      }
+
+      debug!("{} has nothing past byte {}, downloading all of it", url, offset);
+      return self.download(url, dest, expected);
+    }
+
+    // A server that doesn't do ranges sends the whole file instead
+    let mut file = if response.status() == 206 {
+      if response.header("Content-Range").and_then(range_start) != Some(offset) {
+        return Err(DownloadError::Network(Box::new(FetchError::UnexpectedRange(offset))));
+      }
+
+      OpenOptions::new().append(true).open(dest)?
+    } else {
+      debug!("{} ignored the range request, downloading all of it", url);
+      File::create(dest)?
+    };
+    io::copy(&mut response.into_reader(), &mut file)?;
+    file.sync_all()?;
+    Ok(())
+  }
+}
+
+/// The first byte in a `Content-Range` header like `bytes 7-13/14`
+fn range_start(content_range: &str) -> Option<u64> {
+  content_range.strip_prefix("bytes ")?.split('-').next()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+  use super::UreqDownloader;
+  use crate::crypto::sha256_of_reader;
+  use crate::download::{DownloadError, Downloader, ResumeableDownloader};
+  use crate::release_entry::ReleaseEntry;
+  use std::cell::RefCell;
+  use std::fs;
+  use std::io::{BufRead, BufReader, Write};
+  use std::net::TcpListener;
+  use std::thread::{self, JoinHandle};
+
+  /// Answers one request on a local port with `response`, handing back the
+  /// request headers once the connection is done.
+  pub fn serve_once(response: String) -> (String, JoinHandle<String>) {
+    let (url, handle) = serve_all(vec![response]);
+    (url, thread::spawn(move || handle.join().unwrap().remove(0)))
+  }
+
+  /// Like `serve_once`, but answers one request per response, in order
+  pub fn serve_all(responses: Vec<String>) -> (String, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/RELEASES", listener.local_addr().unwrap());
+
+    let handle = thread::spawn(move || {
+      responses.iter().map(|response| {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = String::new();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        loop {
+          let mut line = String::new();
+          reader.read_line(&mut line).unwrap();
+          if line.trim().is_empty() {
+            break;
+          }
+
+          request.push_str(&line);
+        }
+
+        stream.write_all(response.as_bytes()).unwrap();
+        request
+      }).collect()
+    });
+
+    (url, handle)
+  }
+
+  pub fn ok_response(body: &str, etag: &str) -> String {
+    format!("HTTP/1.1 200 OK\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", etag, body.len(), body)
+  }
+
+  pub fn not_modified_response() -> String {
+    "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n".to_owned()
+  }
+
+  fn unavailable_response() -> String {
+    "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned()
+  }
+
+  fn range_not_satisfiable_response() -> String {
+    "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */14\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned()
+  }
+
+  fn entry_for(body: &str) -> ReleaseEntry {
+    let sha256 = sha256_of_reader(body.as_bytes()).unwrap();
+    ReleaseEntry { sha256, length: Some(body.len() as u64), ..ReleaseEntry::default() }
+  }
+
+  #[test]
+  fn ureq_downloader_streams_to_disk() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("myproject.7z");
+
+    let (url, server) = serve_once(ok_response("This is a test", "\"v1\""));
+    UreqDownloader::new().with_bearer_token("hunter2".to_owned()).download(&url, &dest, &entry_for("This is a test")).unwrap();
+
+    assert!(server.join().unwrap().to_lowercase().contains("authorization: bearer hunter2"));
+    assert_eq!(fs::read(&dest).unwrap(), b"This is a test");
+  }
+
+  #[test]
+  fn ureq_downloader_streams_into_writer() {
+    let dir = tempfile::tempdir().unwrap();
+    let scratch = dir.path().join("myproject.7z.part");
+
+    let (url, server) = serve_once(ok_response("This is a test", "\"v1\""));
+    let mut written = Vec::new();
+    UreqDownloader::new().download_to_writer(&url, &mut written, &scratch, &entry_for("This is a test")).unwrap();
+    server.join().unwrap();
+
+    assert_eq!(written, b"This is a test");
+    assert!(!scratch.exists());
+  }
+
+  #[test]
+  fn ureq_downloader_reports_progress() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("myproject.7z");
+    let calls = RefCell::new(Vec::new());
+
+    let (url, server) = serve_once(ok_response("This is a test", "\"v1\""));
+    let entry = ReleaseEntry { length: None, ..entry_for("This is a test") };
+    UreqDownloader::new().download_with_progress(&url, &dest, &entry, &|done, total| calls.borrow_mut().push((done, total))).unwrap();
+    server.join().unwrap();
+
+    // The total comes from Content-Length, as the entry doesn't know it
+    let calls = calls.into_inner();
+    assert!(calls.iter().all(|x| x.1 == 14));
+    assert_eq!(calls.last(), Some(&(14, 14)));
+  }
+
+  #[test]
+  fn ureq_downloader_resumes_with_range_request() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("myproject.7z");
+    fs::write(&dest, "This is").unwrap();
+
+    let response = "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 7-13/14\r\nContent-Length: 7\r\nConnection: close\r\n\r\n a test".to_owned();
+    let (url, server) = serve_once(response);
+    ResumeableDownloader::new(UreqDownloader::new()).download(&url, &dest, &entry_for("This is a test")).unwrap();
+
+    assert!(server.join().unwrap().to_lowercase().contains("range: bytes=7-"));
+    assert_eq!(fs::read(&dest).unwrap(), b"This is a test");
+  }
+
+  #[test]
+  fn ureq_downloader_rejects_range_from_wrong_offset() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("myproject.7z");
+    fs::write(&dest, "This is").unwrap();
+
+    let response = "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 0-13/14\r\nContent-Length: 14\r\nConnection: close\r\n\r\nThis is a test".to_owned();
+    let (url, server) = serve_once(response);
+    match ResumeableDownloader::new(UreqDownloader::new()).download(&url, &dest, &entry_for("This is a test")) {
+      Err(DownloadError::Network(_)) => (),
+      x => panic!("Expected Network, got {:?}", x)
+    }
+    server.join().unwrap();
+    assert_eq!(fs::read(&dest).unwrap(), b"This is");
+  }
+
+  #[test]
+  fn ureq_downloader_verifies_complete_file_on_range_not_satisfiable() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("myproject.7z");
+    fs::write(&dest, "This is a test").unwrap();
+
+    // Without a length, a complete file looks like a partial one
+    let (url, server) = serve_once(range_not_satisfiable_response());
+    let entry = ReleaseEntry { length: None, ..entry_for("This is a test") };
+    ResumeableDownloader::new(UreqDownloader::new()).download(&url, &dest, &entry).unwrap();
+
+    assert!(server.join().unwrap().to_lowercase().contains("range: bytes=14-"));
+    assert_eq!(fs::read(&dest).unwrap(), b"This is a test");
+  }
+
+  #[test]
+  fn ureq_downloader_restarts_on_range_not_satisfiable() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("myproject.7z");
+    fs::write(&dest, "That is a test, but longer").unwrap();
+
+    let (url, server) = serve_all(vec![range_not_satisfiable_response(), ok_response("This is a test", "\"v1\"")]);
+    let entry = ReleaseEntry { length: None, ..entry_for("This is a test") };
+    ResumeableDownloader::new(UreqDownloader::new()).download(&url, &dest, &entry).unwrap();
+
+    assert_eq!(server.join().unwrap().len(), 2);
+    assert_eq!(fs::read(&dest).unwrap(), b"This is a test");
+  }
+
+  #[test]
+  fn ureq_downloader_restarts_when_range_is_ignored() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("myproject.7z");
+    fs::write(&dest, "This is").unwrap();
+
+    let (url, server) = serve_once(ok_response("This is a test", "\"v1\""));
+    ResumeableDownloader::new(UreqDownloader::new()).download(&url, &dest, &entry_for("This is a test")).unwrap();
+    server.join().unwrap();
+    assert_eq!(fs::read(&dest).unwrap(), b"This is a test");
+  }
+
+  #[test]
+  fn ureq_downloader_retries_server_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("myproject.7z");
+
+    let (url, server) = serve_all(vec![unavailable_response(), ok_response("This is a test", "\"v1\"")]);
+    UreqDownloader::new().download(&url, &dest, &entry_for("This is a test")).unwrap();
+
+    assert_eq!(server.join().unwrap().len(), 2);
+    assert_eq!(fs::read(&dest).unwrap(), b"This is a test");
+  }
+
+  #[test]
+  fn ureq_downloader_gives_up_after_max_retries() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("myproject.7z");
+
+    let (url, server) = serve_once(unavailable_response());
+    match UreqDownloader::new().with_max_retries(0).download(&url, &dest, &entry_for("This is a test")) {
+      Err(DownloadError::Network(_)) => (),
+      x => panic!("Expected Network, got {:?}", x)
+    }
+    server.join().unwrap();
+  }
+
+  #[test]
+  fn ureq_downloader_rejects_wrong_hash() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("myproject.7z");
+
+    let (url, server) = serve_once(ok_response("This is a tesT", "\"v1\""));
+    match UreqDownloader::new().download(&url, &dest, &entry_for("This is a test")) {
+      Err(DownloadError::HashMismatch) => (),
+      x => panic!("Expected HashMismatch, got {:?}", x)
+    }
+    server.join().unwrap();
+    assert!(!dest.exists());
+  }
+}
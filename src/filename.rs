@@ -0,0 +1,97 @@
+use crate::package_format::strip_package_extension;
+use regex::Regex;
+use semver::Version;
+
+lazy_static! {
+  static ref VERSION_IN_FILENAME: Regex = Regex::new(r"\d+\.\d+\.\d+(-[0-9A-Za-z.]+)?(\+[0-9A-Za-z.]+)?").unwrap();
+}
+
+/// Finds the first `major.minor.patch[-pre][+build]` in a package filename,
+/// e.g. `1.2.3` in `myapp-1.2.3.7z`. A recognized package extension and a
+/// trailing `-full` or `-delta`, as in `myapp-1.2.3-full.nupkg`, are removed
+/// first so that they aren't taken as part of a pre-release tag.
+pub fn extract_version_from_filename(name: &str) -> Option<Version> {
+  versions_in_filename(name).next()
+}
+
+/// Like `extract_version_from_filename`, but takes the last version in the
+/// name, e.g. `1.2.3` from `app-v1.0.0-to-1.2.3.zip`.
+pub fn version_from_filename(name: &str) -> Option<Version> {
+  versions_in_filename(name).last()
+}
+
+fn versions_in_filename(name: &str) -> impl Iterator<Item = Version> + '_ {
+  VERSION_IN_FILENAME.find_iter(strip_package_suffixes(name))
+    .filter_map(|x| Version::parse(x.as_str().trim_end_matches('.')).ok())
+}
+
+/// `name` without its package extension and any `-full` or `-delta` tag
+fn strip_package_suffixes(name: &str) -> &str {
+  let mut stem = strip_package_extension(name);
+  for suffix in &["-full", "-delta"] {
+    let start = stem.len().saturating_sub(suffix.len());
+    if stem.get(start..).is_some_and(|x| x.eq_ignore_ascii_case(suffix)) {
+      stem = &stem[..start];
+    }
+  }
+
+  stem
+}
+
+/// Whether a package filename marks it as a delta, i.e. contains `-delta`
+/// or `_delta` in any case, as in `myapp-1.2.3-delta.nupkg`.
+pub fn is_delta_from_filename(name: &str) -> bool {
+  let name = name.to_ascii_lowercase();
+  name.contains("-delta") || name.contains("_delta")
+}
+
+#[cfg(test)]
+mod tests {
+  use semver::Version;
+  use super::{extract_version_from_filename, is_delta_from_filename, version_from_filename};
+
+  #[test]
+  fn finds_version_in_filename() {
+    assert_eq!(extract_version_from_filename("myapp-1.2.3.7z"), Some(Version::parse("1.2.3").unwrap()));
+    assert_eq!(extract_version_from_filename("myapp_2.0.0-beta.1.zip"), Some(Version::parse("2.0.0-beta.1").unwrap()));
+    assert_eq!(extract_version_from_filename("myapp-1.2.3.tar.gz"), Some(Version::parse("1.2.3").unwrap()));
+    assert_eq!(extract_version_from_filename("myapp-10.20.30-x64.7z"), Some(Version::parse("10.20.30-x64").unwrap()));
+    assert_eq!(extract_version_from_filename("myapp-1.2.3-full.nupkg"), Some(Version::parse("1.2.3").unwrap()));
+    assert_eq!(extract_version_from_filename("myapp-2.0.0-beta.1-DELTA.nupkg"), Some(Version::parse("2.0.0-beta.1").unwrap()));
+  }
+
+  #[test]
+  fn no_version_gives_none() {
+    assert_eq!(extract_version_from_filename("noversion.7z"), None);
+    assert_eq!(extract_version_from_filename("myapp-1.2.7z"), None);
+    assert_eq!(extract_version_from_filename(""), None);
+    assert_eq!(extract_version_from_filename("\u{2713}ab"), None);
+  }
+
+  #[test]
+  fn version_from_filename_finds_last_version() {
+    let check = |name: &str, expected: &str| {
+      assert_eq!(version_from_filename(name), Some(Version::parse(expected).unwrap()), "{}", name);
+    };
+
+    check("app-1.2.3.7z", "1.2.3");
+    check("app-2.0.0-beta.1-delta.nupkg", "2.0.0-beta.1");
+    check("app-2.0.0-beta.1+build.5-full.nupkg", "2.0.0-beta.1+build.5");
+    check("app-v1.0.0-to-1.2.3.zip", "1.2.3");
+    assert_eq!(extract_version_from_filename("app-1.2.3_patch-1.2.4.7z"), Some(Version::parse("1.2.3").unwrap()));
+    assert_eq!(version_from_filename("app-1.2.3_patch-1.2.4.7z"), Some(Version::parse("1.2.4").unwrap()));
+    assert_eq!(version_from_filename("app.7z"), None);
+    assert_eq!(version_from_filename("app-1.2.7z"), None);
+  }
+
+  #[test]
+  fn detects_delta_filenames() {
+    assert!(is_delta_from_filename("myapp-1.2.3-delta.nupkg"));
+    assert!(is_delta_from_filename("myapp_1.2.3_DELTA.7z"));
+    assert!(is_delta_from_filename("MyApp-Delta-1.2.3.zip"));
+
+    assert!(!is_delta_from_filename("myapp-1.2.3-full.nupkg"));
+    assert!(!is_delta_from_filename("deltaforce-1.2.3.7z"));
+    assert!(!is_delta_from_filename(""));
+  }
+}
@@ -0,0 +1,128 @@
+use crate::release_entry::ReleaseEntry;
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::path::PathBuf;
+
+/// A callback run with the entry being installed
+pub type InstallHook = Box<dyn Fn(&ReleaseEntry)>;
+
+/// Everything that controls where and how updates are installed
+pub struct InstallConfig {
+  /// The directory each version is installed under
+  pub install_dir: PathBuf,
+  /// How many versions to keep installed, including the current one
+  pub keep_versions: usize,
+  pub pre_install: Option<InstallHook>,
+  pub post_install: Option<InstallHook>,
+  /// Where packages are downloaded to, or the system temp directory if `None`
+  pub temp_dir: Option<PathBuf>,
+}
+
+impl InstallConfig {
+  /// The usual per-user install location for `app_name` on this platform:
+  /// `%LOCALAPPDATA%` on Windows, `~/Library/Application Support` on macOS
+  /// and `$XDG_DATA_HOME` or `~/.local/share` elsewhere. Keeps the current
+  /// and previous versions, so that an update can be rolled back.
+  pub fn default_for_app(app_name: &str) -> InstallConfig {
+    InstallConfig {
+      install_dir: data_dir(|x| env::var_os(x)).join(app_name),
+      keep_versions: 2,
+      pre_install: None,
+      post_install: None,
+      temp_dir: None,
+    }
+  }
+}
+
+/// The per-user data directory, looking up environment variables with `var`.
+/// Falls back to the working directory if they aren't set.
+fn data_dir<F: Fn(&str) -> Option<OsString>>(var: F) -> PathBuf {
+  let home = || var("HOME").map(PathBuf::from);
+
+  let dir = if cfg!(windows) {
+    var("LOCALAPPDATA").map(PathBuf::from)
+  } else if cfg!(target_os = "macos") {
+    home().map(|x| x.join("Library").join("Application Support"))
+  } else {
+    var("XDG_DATA_HOME").map(PathBuf::from)
+      .filter(|x| x.is_absolute())
+      .or_else(|| home().map(|x| x.join(".local").join("share")))
+  };
+
+  dir.unwrap_or_default()
+}
+
+impl fmt::Debug for InstallConfig {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("InstallConfig")
+      .field("install_dir", &self.install_dir)
+      .field("keep_versions", &self.keep_versions)
+      .field("pre_install", &self.pre_install.is_some())
+      .field("post_install", &self.post_install.is_some())
+      .field("temp_dir", &self.temp_dir)
+      .finish()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{data_dir, InstallConfig};
+  use std::ffi::OsString;
+  use std::path::PathBuf;
+
+  #[test]
+  fn default_for_app_installs_under_app_name() {
+    let config = InstallConfig::default_for_app("myproject");
+
+    assert!(config.install_dir.ends_with("myproject"));
+    assert_eq!(config.keep_versions, 2);
+    assert!(config.pre_install.is_none() && config.post_install.is_none());
+  }
+
+  #[cfg(all(unix, not(target_os = "macos")))]
+  #[test]
+  fn data_dir_follows_xdg() {
+    let vars = |xdg: Option<&'static str>| move |x: &str| match x {
+      "HOME" => Some(OsString::from("/home/paul")),
+      "XDG_DATA_HOME" => xdg.map(OsString::from),
+      _ => None
+    };
+
+    assert_eq!(data_dir(vars(None)), PathBuf::from("/home/paul/.local/share"));
+    assert_eq!(data_dir(vars(Some("/data"))), PathBuf::from("/data"));
+    assert_eq!(data_dir(vars(Some("relative"))), PathBuf::from("/home/paul/.local/share"));
+    assert_eq!(data_dir(|_| None), PathBuf::new());
+  }
+
+  #[cfg(target_os = "macos")]
+  #[test]
+  fn data_dir_uses_application_support() {
+    let dir = data_dir(|x| if x == "HOME" { Some(OsString::from("/Users/paul")) } else { None });
+    assert_eq!(dir, PathBuf::from("/Users/paul/Library/Application Support"));
+  }
+
+  #[cfg(windows)]
+  #[test]
+  fn data_dir_uses_local_app_data() {
+    let dir = data_dir(|x| if x == "LOCALAPPDATA" { Some(OsString::from("C:\\Users\\paul\\AppData\\Local")) } else { None });
+    assert_eq!(dir, PathBuf::from("C:\\Users\\paul\\AppData\\Local"));
+  }
+
+  #[test]
+  fn hooks_are_called_with_entry() {
+    use crate::release_entry::ReleaseEntry;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let calls = Rc::new(Cell::new(0));
+    let counter = calls.clone();
+    let mut config = InstallConfig::default_for_app("myproject");
+    config.post_install = Some(Box::new(move |x: &ReleaseEntry| counter.set(counter.get() + x.percentage)));
+
+    let entry = ReleaseEntry::parse("e4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject.7z 1.2.3 12345 full").unwrap();
+    (config.post_install.as_ref().unwrap())(&entry);
+    assert_eq!(calls.get(), 100);
+    assert!(format!("{:?}", config).contains("post_install: true"));
+  }
+}
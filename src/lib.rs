@@ -6,17 +6,92 @@
 #[macro_use]
 extern crate lazy_static;
 
-/*
+#[cfg(not(feature = "tracing"))]
 #[macro_use]
 extern crate log;
-*/
 
+// With tracing enabled its macros stand in for the log ones, so the same
+// debug!/warn!/error! calls produce structured events instead.
+#[cfg(feature = "tracing")]
+#[macro_use]
+extern crate tracing;
+
+extern crate base64;
+extern crate chrono;
 extern crate hex;
 extern crate regex;
 extern crate semver;
 extern crate sha2;
 extern crate url;
 
-pub use release_entry::{ReleaseEntry};
+#[cfg(feature = "async")]
+extern crate tokio;
+
+#[cfg(feature = "flate2")]
+extern crate flate2;
+
+#[cfg(feature = "fetch")]
+extern crate serde_json;
+#[cfg(feature = "fetch")]
+extern crate ureq;
+
+#[cfg(feature = "sign")]
+extern crate ed25519_dalek;
+
+#[cfg(feature = "nupkg")]
+extern crate zip;
+
+#[cfg(feature = "parallel")]
+extern crate rayon;
+
+#[cfg(test)]
+extern crate proptest;
+#[cfg(test)]
+extern crate tempfile;
+
+pub use crate::delta::{ChainError, DeltaApplier, DeltaChain};
+pub use crate::download::{DownloadError, Downloader, ResumeableDownloader};
+pub use crate::error::{DuplicateError, ReleaseEntryError};
+pub use crate::filename::{extract_version_from_filename, is_delta_from_filename, version_from_filename};
+#[cfg(feature = "fetch")]
+pub use crate::fetch::{FetchError, UreqDownloader};
+pub use crate::install_config::{InstallConfig, InstallHook};
+#[cfg(feature = "fetch")]
+pub use crate::manifest_cache::{CacheStatus, ManifestCache};
+#[cfg(feature = "nupkg")]
+pub use crate::nupkg::{NupkgError};
+pub use crate::package_cache::{LocalPackageCache};
+pub use crate::package_format::{package_format_from_filename, PackageFormat};
+pub use crate::package_source::{PackageSource};
+pub use crate::package_type::{PackageType};
+pub use crate::release_entry::{Bump, LineWarning, ParseOptions, ReleaseEntry, ReleasesHeader, UpdateReport, Warning};
+pub use crate::release_entry_builder::{ReleaseEntryBuilder};
+pub use crate::release_file::{ReleaseFile, MANIFEST_VERSION};
+pub use crate::sha256_hash::{Sha256Hash, SHA256_BYTES, SHA256_HEX_LEN};
+pub use crate::update_plan::{PlanType, UpdatePlan};
+#[cfg(feature = "sign")]
+pub use crate::sign::{sign_manifest, verify_manifest, SignatureError, SigningKey, VerifyingKey};
 
+pub mod crypto;
+mod delta;
+mod download;
+mod error;
+mod filename;
+#[cfg(feature = "fetch")]
+mod fetch;
+mod install_config;
+#[cfg(feature = "fetch")]
+mod manifest_cache;
+#[cfg(feature = "nupkg")]
+mod nupkg;
+mod package_cache;
+mod package_format;
+mod package_source;
+mod package_type;
 mod release_entry;
+mod release_entry_builder;
+mod release_file;
+mod sha256_hash;
+mod update_plan;
+#[cfg(feature = "sign")]
+mod sign;
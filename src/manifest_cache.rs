@@ -0,0 +1,223 @@
+use crate::crypto::sha256_of_reader;
+use crate::fetch::{self, FetchError};
+use crate::release_file::ReleaseFile;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Each parsed manifest by URL, along with the ETag it was fetched with
+type ParsedManifests = HashMap<String, (Option<String>, ReleaseFile)>;
+
+/// Whether `ManifestCache::get_or_fetch` could reuse the cached manifest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+  Hit,
+  Miss,
+}
+
+/// Keeps the last downloaded copy of each RELEASES file, along with its
+/// ETag, in `dir` so that polling only re-downloads manifests that changed.
+/// Manifests are also kept parsed in memory, shared between clones, so that
+/// a 304 doesn't parse the same file again.
+#[derive(Debug, Clone)]
+pub struct ManifestCache {
+  dir: PathBuf,
+  parsed: Arc<Mutex<ParsedManifests>>,
+}
+
+impl ManifestCache {
+  pub fn new<P: Into<PathBuf>>(dir: P) -> ManifestCache {
+    ManifestCache { dir: dir.into(), parsed: Arc::default() }
+  }
+
+  /// Fetches `url`, sending the cached ETag if there is one. A 304 returns
+  /// the cached manifest, anything else replaces it. Failing to write the
+  /// cache is only logged, the new manifest is still returned.
+  pub fn get_or_fetch(&self, url: &str) -> Result<(ReleaseFile, CacheStatus), FetchError> {
+    let cached = self.read(url);
+    let etag = cached.as_ref().and_then(|x| x.1.as_deref());
+
+    match fetch::get_manifest(url, etag, FETCH_TIMEOUT)? {
+      Some((content, etag)) => {
+        let file = ReleaseFile::parse(&content)?;
+        // Like a corrupt cache, one that can't be written never blocks updates
+        if let Err(err) = self.write(url, &content, etag.as_deref()) {
+          warn!("Couldn't cache the manifest for {}: {}", url, err);
+        }
+        self.parsed().insert(url.to_owned(), (etag, file.clone()));
+        Ok((file, CacheStatus::Miss))
+      },
+      None => match cached {
+        Some((content, etag)) => {
+          // Only reuse the parsed copy if it's of the version on disk, which
+          // another process may have replaced
+          if let Some((parsed_etag, file)) = self.parsed().get(url) {
+            if *parsed_etag == etag {
+              return Ok((file.clone(), CacheStatus::Hit));
+            }
+          }
+
+          let file = ReleaseFile::parse(&content)?;
+          self.parsed().insert(url.to_owned(), (etag, file.clone()));
+          Ok((file, CacheStatus::Hit))
+        },
+        None => Err(FetchError::UnexpectedStatus(304))
+      }
+    }
+  }
+
+  /// Removes the cached copy of `url`, if there is one
+  pub fn invalidate(&self, url: &str) -> Result<(), io::Error> {
+    self.parsed().remove(url);
+    match fs::remove_file(self.path_for(url)) {
+      Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+      x => x
+    }
+  }
+
+  fn path_for(&self, url: &str) -> PathBuf {
+    let key = sha256_of_reader(url.as_bytes()).expect("reading from memory can't fail");
+    self.dir.join(format!("{}.json", key))
+  }
+
+  /// The cached content and ETag for `url`. A cache file that can't be read
+  /// is treated as missing, so that a corrupt cache never blocks updates.
+  fn read(&self, url: &str) -> Option<(String, Option<String>)> {
+    let text = fs::read_to_string(self.path_for(url)).ok()?;
+    let value = match serde_json::from_str::<Value>(&text) {
+      Ok(value) => value,
+      Err(err) => {
+        warn!("Ignoring corrupt manifest cache for {}: {}", url, err);
+        return None;
+      }
+    };
+
+    let content = value["content"].as_str()?.to_owned();
+    let etag = value["etag"].as_str().map(|x| x.to_owned());
+    Some((content, etag))
+  }
+
+  /// Replaces the cached copy of `url` through a temporary file, so that a
+  /// crash part way through never leaves a truncated cache behind
+  fn write(&self, url: &str, content: &str, etag: Option<&str>) -> Result<(), io::Error> {
+    fs::create_dir_all(&self.dir)?;
+    let value = json!({ "url": url, "etag": etag, "content": content });
+    let path = self.path_for(url);
+    let tmp = path.with_extension("json.tmp");
+
+    let mut file = File::create(&tmp)?;
+    file.write_all(value.to_string().as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp, path).inspect_err(|_| {
+      let _ = fs::remove_file(&tmp);
+    })
+  }
+
+  /// The parsed manifests, which stay usable even if a thread panicked
+  /// while holding the lock
+  fn parsed(&self) -> MutexGuard<'_, ParsedManifests> {
+    self.parsed.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{CacheStatus, ManifestCache};
+  use crate::fetch::tests::{not_modified_response, ok_response, serve_all, serve_once};
+  use serde_json::json;
+  use std::fs;
+
+  const FIXTURE: &str = "
+e4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-1.0.0.7z 1.0.0 12345 full
+a4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-1.1.0.7z 1.1.0 12345 full";
+
+  #[test]
+  fn get_or_fetch_reuses_cached_manifest() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = ManifestCache::new(dir.path().join("cache"));
+
+    let (url, server) = serve_once(ok_response(FIXTURE, "\"v1\""));
+    let (file, status) = cache.get_or_fetch(&url).unwrap();
+    server.join().unwrap();
+    assert_eq!(file.len(), 2);
+    assert_eq!(status, CacheStatus::Miss);
+
+    // Each server gets a new port, so carry the cache file over to the new URL
+    let old_path = cache.path_for(&url);
+    let (url, server) = serve_once(not_modified_response());
+    fs::rename(old_path, cache.path_for(&url)).unwrap();
+
+    let (file, status) = cache.get_or_fetch(&url).unwrap();
+    assert!(server.join().unwrap().to_lowercase().contains("if-none-match: \"v1\""));
+    assert_eq!(file.len(), 2);
+    assert_eq!(status, CacheStatus::Hit);
+  }
+
+  #[test]
+  fn get_or_fetch_keeps_parsed_manifest_in_memory() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = ManifestCache::new(dir.path());
+
+    let (url, server) = serve_all(vec![ok_response(FIXTURE, "\"v1\""), not_modified_response()]);
+    cache.get_or_fetch(&url).unwrap();
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+    // Had the 304 parsed the file on disk, it would find no entries
+    fs::write(cache.path_for(&url), json!({ "url": url, "etag": "\"v1\"", "content": "" }).to_string()).unwrap();
+    let (file, status) = cache.get_or_fetch(&url).unwrap();
+    server.join().unwrap();
+    assert_eq!(file.len(), 2);
+    assert_eq!(status, CacheStatus::Hit);
+  }
+
+  #[test]
+  fn get_or_fetch_works_without_writable_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    // A file where the cache directory should be, so nothing can be written
+    let path = dir.path().join("cache");
+    fs::write(&path, "").unwrap();
+    let cache = ManifestCache::new(&path);
+
+    let (url, server) = serve_once(ok_response(FIXTURE, "\"v1\""));
+    let (file, status) = cache.get_or_fetch(&url).unwrap();
+    server.join().unwrap();
+    assert_eq!(file.len(), 2);
+    assert_eq!(status, CacheStatus::Miss);
+  }
+
+  #[test]
+  fn invalidate_removes_cached_manifest() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = ManifestCache::new(dir.path());
+
+    let (url, server) = serve_once(ok_response(FIXTURE, "\"v1\""));
+    cache.get_or_fetch(&url).unwrap();
+    server.join().unwrap();
+    assert!(cache.path_for(&url).exists());
+
+    cache.invalidate(&url).unwrap();
+    assert!(!cache.path_for(&url).exists());
+    cache.invalidate(&url).unwrap();
+  }
+
+  #[test]
+  fn corrupt_cache_is_refetched() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = ManifestCache::new(dir.path());
+
+    let (url, server) = serve_once(ok_response(FIXTURE, "\"v2\""));
+    fs::write(cache.path_for(&url), "not json").unwrap();
+
+    let (_, status) = cache.get_or_fetch(&url).unwrap();
+    assert!(!server.join().unwrap().to_lowercase().contains("if-none-match"));
+    assert_eq!(status, CacheStatus::Miss);
+  }
+}
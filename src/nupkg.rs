@@ -0,0 +1,100 @@
+use regex::Regex;
+use semver::{SemVerError, Version};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Seek};
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+/// Everything that can go wrong while reading a NuGet package
+#[derive(Debug)]
+pub enum NupkgError {
+  Io(io::Error),
+  Zip(ZipError),
+  MissingNuspec,
+  MissingVersion,
+  InvalidVersion(SemVerError),
+}
+
+impl fmt::Display for NupkgError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      NupkgError::Io(ref err) => write!(f, "{}", err),
+      NupkgError::Zip(ref err) => write!(f, "package is not a valid ZIP archive: {}", err),
+      NupkgError::MissingNuspec => write!(f, "package does not contain a .nuspec file"),
+      NupkgError::MissingVersion => write!(f, ".nuspec file has no <version> element"),
+      NupkgError::InvalidVersion(ref err) => write!(f, "invalid version in .nuspec file: {}", err),
+    }
+  }
+}
+
+impl Error for NupkgError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match *self {
+      NupkgError::Io(ref err) => Some(err),
+      NupkgError::Zip(ref err) => Some(err),
+      NupkgError::InvalidVersion(ref err) => Some(err),
+      _ => None
+    }
+  }
+}
+
+impl From<io::Error> for NupkgError {
+  fn from(err: io::Error) -> NupkgError {
+    NupkgError::Io(err)
+  }
+}
+
+impl From<ZipError> for NupkgError {
+  fn from(err: ZipError) -> NupkgError {
+    NupkgError::Zip(err)
+  }
+}
+
+lazy_static! {
+  static ref VERSION_ELEMENT: Regex = Regex::new(r"<version>\s*([^<]*?)\s*</version>").unwrap();
+}
+
+/// Reads the `<version>` out of the `.nuspec` file at the root of a NuGet
+/// package.
+pub(crate) fn read_nuspec_version<R: Read + Seek>(package: R) -> Result<Version, NupkgError> {
+  let mut archive = ZipArchive::new(package)?;
+  let name = archive.file_names()
+    .find(|x| !x.contains('/') && x.to_ascii_lowercase().ends_with(".nuspec"))
+    .map(|x| x.to_owned())
+    .ok_or(NupkgError::MissingNuspec)?;
+
+  let mut nuspec = String::new();
+  archive.by_name(&name)?.read_to_string(&mut nuspec)?;
+
+  let version = VERSION_ELEMENT.captures(&nuspec)
+    .and_then(|x| x.get(1))
+    .ok_or(NupkgError::MissingVersion)?;
+  Version::parse(version.as_str()).map_err(NupkgError::InvalidVersion)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+  use std::fs::File;
+  use std::io::Write;
+  use std::path::Path;
+  use zip::write::SimpleFileOptions;
+  use zip::ZipWriter;
+
+  /// Writes a minimal NuGet package whose `.nuspec` carries `version`
+  pub fn write_nupkg(path: &Path, version: &str) {
+    let mut zip = ZipWriter::new(File::create(path).unwrap());
+    zip.start_file("myproject.nuspec", SimpleFileOptions::default()).unwrap();
+    write!(zip, "<?xml version=\"1.0\"?>
+<package xmlns=\"http://schemas.microsoft.com/packaging/2010/07/nuspec.xsd\">
+  <metadata>
+    <id>myproject</id>
+    <version>{}</version>
+  </metadata>
+</package>", version).unwrap();
+
+    zip.start_file("lib/net45/myproject.exe", SimpleFileOptions::default()).unwrap();
+    zip.write_all(b"This is a test").unwrap();
+    zip.finish().unwrap();
+  }
+}
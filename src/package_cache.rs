@@ -0,0 +1,210 @@
+use crate::release_entry::ReleaseEntry;
+use crate::sha256_hash::Sha256Hash;
+use semver::Version;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Keeps downloaded packages in `dir`, in one directory per version and named
+/// by their SHA256, so that a retry or a rollback doesn't have to download
+/// them again.
+#[derive(Debug, Clone)]
+pub struct LocalPackageCache {
+  dir: PathBuf,
+}
+
+impl LocalPackageCache {
+  pub fn new<P: Into<PathBuf>>(dir: P) -> LocalPackageCache {
+    LocalPackageCache { dir: dir.into() }
+  }
+
+  /// Adds a copy of the package at `source` as `entry`. It's copied rather
+  /// than hard linked, as downloaders truncate and rewrite their destination
+  /// in place, which would corrupt a linked copy too. The copy is checked
+  /// against the entry's size and SHA256 before being renamed into place,
+  /// failing with `InvalidData` if it doesn't match. A package that's already
+  /// cached and intact is left alone.
+  pub fn store(&self, entry: &ReleaseEntry, source: &Path) -> Result<(), io::Error> {
+    let dir = self.dir.join(entry.version.to_string());
+    let dest = dir.join(entry.sha256_hex());
+    if entry.verify_file(&dest).is_ok() {
+      return Ok(());
+    }
+
+    fs::create_dir_all(&dir)?;
+    let tmp = dest.with_extension("tmp");
+    let result = fs::copy(source, &tmp)
+      .and_then(|_| entry.verify_file(&tmp).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)))
+      .and_then(|_| fs::rename(&tmp, &dest));
+
+    result.inspect_err(|_| {
+      let _ = fs::remove_file(&tmp);
+    })
+  }
+
+  /// The path of the cached package with this SHA256, if there is one
+  pub fn retrieve(&self, sha256: &Sha256Hash) -> Option<PathBuf> {
+    let name = sha256.to_string();
+    self.versions().ok()?.into_iter()
+      .map(|(_, dir)| dir.join(&name))
+      .find(|x| x.is_file())
+  }
+
+  /// Removes every package except those of the newest `versions_to_keep`
+  /// versions, returning how many bytes that freed.
+  pub fn evict_older_than(&self, versions_to_keep: usize) -> Result<u64, io::Error> {
+    let mut versions = self.versions()?;
+    versions.sort_by(|a, b| b.0.cmp(&a.0));
+
+    let mut freed = 0;
+    for (version, dir) in versions.into_iter().skip(versions_to_keep) {
+      debug!("Evicting {} from the package cache", version);
+      freed += dir_size(&dir)?;
+      fs::remove_dir_all(&dir)?;
+    }
+
+    Ok(freed)
+  }
+
+  /// The size of every cached package, in bytes
+  pub fn total_size(&self) -> Result<u64, io::Error> {
+    self.versions()?.iter().map(|(_, dir)| dir_size(dir)).sum()
+  }
+
+  /// Each version directory in the cache. Anything else in `dir` is left
+  /// alone, and a cache that doesn't exist yet is empty.
+  fn versions(&self) -> Result<Vec<(Version, PathBuf)>, io::Error> {
+    let entries = match fs::read_dir(&self.dir) {
+      Ok(entries) => entries,
+      Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(err) => return Err(err)
+    };
+
+    let mut ret = Vec::new();
+    for entry in entries {
+      let entry = entry?;
+      let version = entry.file_name().to_str().and_then(|x| Version::parse(x).ok());
+      if let Some(version) = version {
+        if entry.file_type()?.is_dir() {
+          ret.push((version, entry.path()));
+        }
+      }
+    }
+
+    Ok(ret)
+  }
+}
+
+fn dir_size(dir: &Path) -> Result<u64, io::Error> {
+  let mut ret = 0;
+  for entry in fs::read_dir(dir)? {
+    ret += entry?.metadata()?.len();
+  }
+
+  Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::LocalPackageCache;
+  use crate::crypto::sha256_of_reader;
+  use crate::release_entry::ReleaseEntry;
+  use semver::Version;
+  use std::fs;
+  use std::io;
+  use std::path::Path;
+
+  fn store(cache: &LocalPackageCache, dir: &Path, version: &str, contents: &str) -> ReleaseEntry {
+    let path = dir.join(format!("myproject-{}.7z", version));
+    fs::write(&path, contents).unwrap();
+
+    let entry = ReleaseEntry {
+      version: Version::parse(version).unwrap(),
+      sha256: sha256_of_reader(contents.as_bytes()).unwrap(),
+      length: Some(contents.len() as u64),
+      ..ReleaseEntry::default()
+    };
+    cache.store(&entry, &path).unwrap();
+    entry
+  }
+
+  #[test]
+  fn store_then_retrieve() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = LocalPackageCache::new(dir.path().join("cache"));
+    assert_eq!(cache.retrieve(&ReleaseEntry::default().sha256), None);
+
+    let entry = store(&cache, dir.path(), "1.0.0", "This is a test");
+    let path = cache.retrieve(&entry.sha256).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "This is a test");
+    entry.verify_file(&path).unwrap();
+
+    // Storing it again is fine
+    cache.store(&entry, &dir.path().join("myproject-1.0.0.7z")).unwrap();
+    assert_eq!(cache.total_size().unwrap(), 14);
+  }
+
+  #[test]
+  fn store_is_not_affected_by_rewriting_source() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = LocalPackageCache::new(dir.path().join("cache"));
+    let entry = store(&cache, dir.path(), "1.0.0", "This is a test");
+
+    // What a downloader does when it fetches the package again
+    fs::write(dir.path().join("myproject-1.0.0.7z"), "Truncated").unwrap();
+    entry.verify_file(cache.retrieve(&entry.sha256).unwrap()).unwrap();
+  }
+
+  #[test]
+  fn store_replaces_corrupt_cache_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = LocalPackageCache::new(dir.path().join("cache"));
+    let entry = store(&cache, dir.path(), "1.0.0", "This is a test");
+
+    let path = cache.retrieve(&entry.sha256).unwrap();
+    fs::write(&path, "This is a tesT").unwrap();
+    cache.store(&entry, &dir.path().join("myproject-1.0.0.7z")).unwrap();
+    entry.verify_file(&path).unwrap();
+  }
+
+  #[test]
+  fn store_rejects_package_not_matching_entry() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = LocalPackageCache::new(dir.path().join("cache"));
+    let entry = store(&cache, dir.path(), "1.0.0", "This is a test");
+
+    let other = dir.path().join("other.7z");
+    fs::write(&other, "Something else").unwrap();
+    let entry = ReleaseEntry { version: Version::parse("1.1.0").unwrap(), ..entry };
+    let err = cache.store(&entry, &other).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(cache.retrieve(&entry.sha256).unwrap(), dir.path().join("cache").join("1.0.0").join(entry.sha256_hex()));
+    assert_eq!(fs::read_dir(dir.path().join("cache").join("1.1.0")).unwrap().count(), 0);
+  }
+
+  #[test]
+  fn evict_older_than_keeps_newest_versions() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = LocalPackageCache::new(dir.path().join("cache"));
+    let old = store(&cache, dir.path(), "1.0.0", "Version one");
+    let middle = store(&cache, dir.path(), "1.10.0", "Version ten");
+    let new = store(&cache, dir.path(), "2.0.0", "Version two!");
+    assert_eq!(cache.total_size().unwrap(), 11 + 11 + 12);
+
+    assert_eq!(cache.evict_older_than(2).unwrap(), 11);
+    assert_eq!(cache.retrieve(&old.sha256), None);
+    assert!(cache.retrieve(&middle.sha256).is_some());
+    assert!(cache.retrieve(&new.sha256).is_some());
+    assert_eq!(cache.total_size().unwrap(), 11 + 12);
+
+    assert_eq!(cache.evict_older_than(2).unwrap(), 0);
+  }
+
+  #[test]
+  fn missing_cache_is_empty() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = LocalPackageCache::new(dir.path().join("cache"));
+    assert_eq!(cache.total_size().unwrap(), 0);
+    assert_eq!(cache.evict_older_than(0).unwrap(), 0);
+  }
+}
@@ -0,0 +1,68 @@
+/// The archive format of a package, going by its file extension
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PackageFormat {
+  SevenZip,
+  Zip,
+  TarGz,
+  TarXz,
+  Nupkg,
+  AppImage,
+  Unknown,
+}
+
+const EXTENSIONS: [(&str, PackageFormat); 8] = [
+  (".7z", PackageFormat::SevenZip),
+  (".zip", PackageFormat::Zip),
+  (".tar.gz", PackageFormat::TarGz),
+  (".tgz", PackageFormat::TarGz),
+  (".tar.xz", PackageFormat::TarXz),
+  (".txz", PackageFormat::TarXz),
+  (".nupkg", PackageFormat::Nupkg),
+  (".appimage", PackageFormat::AppImage),
+];
+
+fn find_extension(name: &str) -> Option<&'static (&'static str, PackageFormat)> {
+  let name = name.to_ascii_lowercase();
+  EXTENSIONS.iter().find(|x| name.ends_with(x.0))
+}
+
+/// Guesses the format of the package called `name` from its extension,
+/// ignoring case.
+pub fn package_format_from_filename(name: &str) -> PackageFormat {
+  find_extension(name).map_or(PackageFormat::Unknown, |x| x.1)
+}
+
+/// `name` without any package extension that `package_format_from_filename`
+/// recognizes
+pub(crate) fn strip_package_extension(name: &str) -> &str {
+  match find_extension(name) {
+    Some(&(extension, _)) => &name[..name.len() - extension.len()],
+    None => name
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{package_format_from_filename, PackageFormat};
+
+  #[test]
+  fn format_comes_from_extension() {
+    assert_eq!(package_format_from_filename("myproject.7z"), PackageFormat::SevenZip);
+    assert_eq!(package_format_from_filename("myproject.zip"), PackageFormat::Zip);
+    assert_eq!(package_format_from_filename("myproject-1.2.3.tar.gz"), PackageFormat::TarGz);
+    assert_eq!(package_format_from_filename("myproject.tgz"), PackageFormat::TarGz);
+    assert_eq!(package_format_from_filename("myproject.tar.xz"), PackageFormat::TarXz);
+    assert_eq!(package_format_from_filename("myproject-1.2.3-full.nupkg"), PackageFormat::Nupkg);
+    assert_eq!(package_format_from_filename("MyProject.AppImage"), PackageFormat::AppImage);
+    assert_eq!(package_format_from_filename("MYPROJECT.7Z"), PackageFormat::SevenZip);
+  }
+
+  #[test]
+  fn unknown_extensions_are_unknown() {
+    assert_eq!(package_format_from_filename("myproject.exe"), PackageFormat::Unknown);
+    assert_eq!(package_format_from_filename("myproject"), PackageFormat::Unknown);
+    assert_eq!(package_format_from_filename("myproject.tar"), PackageFormat::Unknown);
+    assert_eq!(package_format_from_filename(""), PackageFormat::Unknown);
+  }
+}
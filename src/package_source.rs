@@ -0,0 +1,55 @@
+use std::fmt;
+use url::Url;
+
+/// Where a package lives: a file next to the RELEASES file, or an absolute
+/// HTTPS URL
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PackageSource {
+  Filename(String),
+  Url(Url),
+}
+
+impl PackageSource {
+  pub fn as_str(&self) -> &str {
+    match *self {
+      PackageSource::Filename(ref name) => name,
+      PackageSource::Url(ref url) => url.as_str(),
+    }
+  }
+}
+
+impl fmt::Display for PackageSource {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+impl From<PackageSource> for String {
+  fn from(source: PackageSource) -> String {
+    match source {
+      PackageSource::Filename(name) => name,
+      PackageSource::Url(url) => url.into_string(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::PackageSource;
+  use url::Url;
+
+  #[test]
+  fn display_emits_raw_string() {
+    let file = PackageSource::Filename("my project.7z".to_owned());
+    assert_eq!(file.to_string(), "my project.7z");
+
+    let url = PackageSource::Url(Url::parse("https://example.com/myproject.7z").unwrap());
+    assert_eq!(url.to_string(), "https://example.com/myproject.7z");
+  }
+
+  #[test]
+  fn converts_into_string() {
+    let url = PackageSource::Url(Url::parse("https://example.com/myproject.7z").unwrap());
+    assert_eq!(String::from(url), "https://example.com/myproject.7z");
+  }
+}
@@ -0,0 +1,20 @@
+use std::fmt;
+
+/// The kind of package an entry points at, from the delta/full column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PackageType {
+  Full,
+  Delta,
+  /// An internal-only full package that clients may want to filter out
+  Staging,
+}
+
+impl fmt::Display for PackageType {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      PackageType::Full => write!(f, "full"),
+      PackageType::Delta => write!(f, "delta"),
+      PackageType::Staging => write!(f, "staging"),
+    }
+  }
+}
@@ -0,0 +1,152 @@
+use crate::error::ReleaseEntryError;
+use crate::package_source::PackageSource;
+use crate::package_type::PackageType;
+use crate::release_entry::ReleaseEntry;
+use crate::sha256_hash::{Sha256Hash, SHA256_BYTES};
+use semver::Version;
+
+/// Assembles a `ReleaseEntry` field by field, e.g. when authoring a RELEASES
+/// file from freshly built packages.
+#[derive(Debug, Clone, Default)]
+pub struct ReleaseEntryBuilder {
+  sha256: Option<Sha256Hash>,
+  source: Option<PackageSource>,
+  version: Option<Version>,
+  length: Option<u64>,
+  package_type: Option<PackageType>,
+  percentage: Option<i32>,
+}
+
+impl ReleaseEntryBuilder {
+  pub fn new() -> ReleaseEntryBuilder {
+    ReleaseEntryBuilder::default()
+  }
+
+  pub fn sha256_hex(&mut self, sha256: &str) -> Result<&mut Self, ReleaseEntryError> {
+    self.sha256 = Some(ReleaseEntry::sha256_from_hex(sha256)?);
+    Ok(self)
+  }
+
+  pub fn sha256_bytes(&mut self, sha256: [u8; SHA256_BYTES]) -> &mut Self {
+    self.sha256 = Some(Sha256Hash::from(sha256));
+    self
+  }
+
+  pub fn source(&mut self, source: PackageSource) -> &mut Self {
+    self.source = Some(source);
+    self
+  }
+
+  pub fn version(&mut self, version: Version) -> &mut Self {
+    self.version = Some(version);
+    self
+  }
+
+  pub fn length(&mut self, length: u64) -> &mut Self {
+    self.length = Some(length);
+    self
+  }
+
+  pub fn delta(&mut self, is_delta: bool) -> &mut Self {
+    self.package_type = Some(if is_delta { PackageType::Delta } else { PackageType::Full });
+    self
+  }
+
+  pub fn package_type(&mut self, package_type: PackageType) -> &mut Self {
+    self.package_type = Some(package_type);
+    self
+  }
+
+  pub fn percentage(&mut self, percentage: i32) -> &mut Self {
+    self.percentage = Some(percentage);
+    self
+  }
+
+  pub fn build(&self) -> Result<ReleaseEntry, ReleaseEntryError> {
+    let percentage = self.percentage.unwrap_or(100);
+    if !(0..=100).contains(&percentage) {
+      return Err(ReleaseEntryError::InvalidPercentage { value: percentage.to_string() });
+    }
+
+    Ok(ReleaseEntry {
+      sha256: self.sha256.ok_or(ReleaseEntryError::MissingField("sha256"))?,
+      source: self.source.clone().ok_or(ReleaseEntryError::MissingField("source"))?,
+      version: self.version.clone().ok_or(ReleaseEntryError::MissingField("version"))?,
+      version_raw: self.version.as_ref().map(|x| x.to_string()).unwrap_or_default(),
+      length: Some(self.length.ok_or(ReleaseEntryError::MissingField("length"))?),
+      package_type: self.package_type.unwrap_or(PackageType::Full),
+      percentage,
+      signature: None,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use sha2::{Digest, Sha256};
+  use semver::Version;
+  use std::fs;
+  use super::ReleaseEntryBuilder;
+  use crate::package_source::PackageSource;
+  use crate::error::ReleaseEntryError;
+  use crate::release_entry::ReleaseEntry;
+
+  #[test]
+  fn build_from_hex_matches_parsed_entry() {
+    let entry = ReleaseEntryBuilder::new()
+      .sha256_hex("e4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35").unwrap()
+      .source(PackageSource::Filename("myproject.7z".to_owned()))
+      .version(Version::parse("1.2.3").unwrap())
+      .length(12345)
+      .build().unwrap();
+
+    let input = "e4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject.7z 1.2.3 12345 full";
+    assert_eq!(entry, ReleaseEntry::parse(input).unwrap());
+  }
+
+  #[test]
+  fn build_from_digest_bytes_verifies_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("myproject.7z");
+    fs::write(&path, "This is a test").unwrap();
+
+    let mut sha = Sha256::default();
+    sha.input(b"This is a test");
+    let mut digest = [0; 32];
+    digest.copy_from_slice(&sha.result());
+
+    let entry = ReleaseEntryBuilder::new()
+      .sha256_bytes(digest)
+      .source(PackageSource::Filename("myproject.7z".to_owned()))
+      .version(Version::parse("1.2.3").unwrap())
+      .length(14)
+      .build().unwrap();
+
+    assert_eq!(entry.sha256.as_bytes(), &digest);
+    entry.verify_file(&path).unwrap();
+  }
+
+  #[test]
+  fn build_should_fail_missing_fields() {
+    match ReleaseEntryBuilder::new().source(PackageSource::Filename("myproject.7z".to_owned())).build() {
+      Err(ReleaseEntryError::MissingField("sha256")) => (),
+      x => panic!("Expected MissingField, got {:?}", x)
+    }
+  }
+
+  #[test]
+  fn build_should_fail_invalid_percentage() {
+    let result = ReleaseEntryBuilder::new()
+      .sha256_bytes([0; 32])
+      .source(PackageSource::Filename("myproject.7z".to_owned()))
+      .version(Version::parse("1.2.3").unwrap())
+      .length(14)
+      .percentage(150)
+      .build();
+
+    match result {
+      Err(ReleaseEntryError::InvalidPercentage { .. }) => (),
+      x => panic!("Expected InvalidPercentage, got {:?}", x)
+    }
+  }
+}
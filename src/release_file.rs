@@ -0,0 +1,1047 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use crate::delta::DeltaChain;
+use crate::error::{DuplicateError, ReleaseEntryError};
+#[cfg(feature = "fetch")]
+use crate::fetch::{self, FetchError};
+use crate::filename::is_delta_from_filename;
+use crate::package_format::{package_format_from_filename, PackageFormat};
+use crate::package_source::PackageSource;
+use crate::package_type::PackageType;
+use crate::sha256_hash::Sha256Hash;
+use crate::update_plan::{self, UpdatePlan};
+use crate::release_entry::{ReleaseEntry, ReleasesHeader};
+use crate::release_entry_builder::ReleaseEntryBuilder;
+#[cfg(feature = "sign")]
+use crate::sign::{verify_manifest, SignatureError, VerifyingKey};
+use semver::Version;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::iter::FromIterator;
+use std::ops::Index;
+use std::path::Path;
+use std::slice;
+#[cfg(feature = "fetch")]
+use std::time::Duration;
+use std::vec;
+
+/// The comment that marks when a RELEASES file should be re-fetched, e.g.
+/// `# expires: 2025-06-01T00:00:00Z`
+const EXPIRES_DIRECTIVE: &str = "expires:";
+/// The comment naming the manifest format, e.g. `# squirrel-manifest-version: 1`.
+/// A `#!RELEASES v1` header (see `ReleasesHeader`) means the same thing.
+const MANIFEST_VERSION_DIRECTIVE: &str = "squirrel-manifest-version:";
+/// The newest manifest format this parser understands
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// The full set of entries in a RELEASES file
+#[derive(Debug, Clone, Default)]
+pub struct ReleaseFile {
+  entries: Vec<ReleaseEntry>,
+  expiry: Option<DateTime<Utc>>,
+  format_version: Option<u32>,
+}
+
+impl ReleaseFile {
+  pub fn parse(content: &str) -> Result<ReleaseFile, ReleaseEntryError> {
+    let mut ret = ReleaseFile::from(ReleaseEntry::parse_entries(content)?);
+    for (i, line) in content.lines().enumerate() {
+      // Wrapped the same way as in `from_reader`
+      if let Some(comment) = line.trim_start().strip_prefix('#') {
+        ret.read_comment(comment).map_err(|err| ReleaseEntryError::AtLine { line: i + 1, error: Box::new(err) })?;
+      }
+    }
+
+    Ok(ret)
+  }
+
+  /// Parses a RELEASES file line by line, skipping a UTF-8 byte order mark
+  /// if one was written at the start.
+  pub fn from_reader<R: BufRead>(mut reader: R) -> Result<ReleaseFile, ReleaseEntryError> {
+    if reader.fill_buf()?.starts_with(b"\xEF\xBB\xBF") {
+      reader.consume(3);
+    }
+
+    let mut ret = ReleaseFile::default();
+    ret.entries = ReleaseEntry::parse_reader_with_comments(reader, |comment| ret.read_comment(comment))?;
+    Ok(ret)
+  }
+
+  /// Picks up the metadata directives from a comment, ignoring any other
+  /// comment. Where a directive is repeated the last one counts.
+  fn read_comment(&mut self, comment: &str) -> Result<(), ReleaseEntryError> {
+    let comment = comment.trim();
+
+    if let Some(value) = comment.strip_prefix(EXPIRES_DIRECTIVE) {
+      let expiry = DateTime::parse_from_rfc3339(value.trim()).map_err(ReleaseEntryError::InvalidExpiry)?;
+      self.expiry = Some(expiry.with_timezone(&Utc));
+    } else if let Some(value) = comment.strip_prefix(MANIFEST_VERSION_DIRECTIVE) {
+      let version = value.trim().parse::<u32>()
+        .map_err(|_| ReleaseEntryError::UnknownDirective { value: comment.to_owned() })?;
+      self.set_format_version(version);
+    } else if comment.starts_with("!RELEASES") {
+      let header = ReleasesHeader::parse(&format!("#{}", comment))?;
+      self.set_format_version(header.format_version);
+    }
+
+    Ok(())
+  }
+
+  fn set_format_version(&mut self, version: u32) {
+    // Newer formats are expected to stay readable line by line, so carry on
+    // rather than refusing every update
+    if version > MANIFEST_VERSION {
+      warn!("RELEASES file is manifest version {}, this parser only knows up to {}", version, MANIFEST_VERSION);
+    }
+
+    self.format_version = Some(version);
+  }
+
+  /// Reads the RELEASES file at `path`, naming it in any error
+  pub fn from_file(path: &Path) -> Result<ReleaseFile, ReleaseEntryError> {
+    File::open(path)
+      .map_err(ReleaseEntryError::from)
+      .and_then(|x| ReleaseFile::from_reader(BufReader::new(x)))
+      .map_err(|err| ReleaseEntryError::InFile { path: path.to_owned(), error: Box::new(err) })
+  }
+
+  /// Builds a RELEASES file for the packages in `dir`, all at `version`.
+  /// Files are picked up by their extension (see `PackageFormat`) and are
+  /// deltas if `is_delta_from_filename` says so. Subdirectories are skipped.
+  pub fn generate_from_dir(dir: &Path, version: &Version) -> Result<ReleaseFile, ReleaseEntryError> {
+    let mut ret = Vec::new();
+    for item in fs::read_dir(dir)? {
+      let item = item?;
+      let name = item.file_name().to_string_lossy().into_owned();
+      if !item.file_type()?.is_file() || package_format_from_filename(&name) == PackageFormat::Unknown {
+        continue;
+      }
+
+      let path = item.path();
+      let entry = ReleaseEntryBuilder::new()
+        .sha256_bytes(*Sha256Hash::from_file(&path)?.as_bytes())
+        .length(item.metadata()?.len())
+        .delta(is_delta_from_filename(&name))
+        .source(PackageSource::Filename(name))
+        .version(version.clone())
+        .build()?;
+      ret.push(entry);
+    }
+
+    // read_dir order depends on the filesystem
+    ret.sort_by(|a, b| a.filename().cmp(b.filename()));
+    Ok(ReleaseFile::from(ret))
+  }
+
+  /// Writes the RELEASES file to `path` via a temporary file in the same
+  /// directory, so that readers never see a half-written manifest.
+  pub fn write_to_file(&self, path: &Path) -> Result<(), io::Error> {
+    let tmp = path.with_extension("releases.tmp");
+
+    let mut file = File::create(&tmp)?;
+    file.write_all(self.to_string_with_header().as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp, path).inspect_err(|_| {
+      let _ = fs::remove_file(&tmp);
+    })
+  }
+
+  /// When the manifest's `# expires:` comment says it should be re-fetched.
+  /// If there are several, the last one counts.
+  pub fn expiry(&self) -> Option<DateTime<Utc>> {
+    self.expiry
+  }
+
+  pub fn set_expiry(&mut self, expiry: Option<DateTime<Utc>>) {
+    self.expiry = expiry;
+  }
+
+  /// The version from the manifest's `# squirrel-manifest-version:` comment
+  /// or `#!RELEASES v<n>` header, if it has one.
+  pub fn format_version(&self) -> Option<u32> {
+    self.format_version
+  }
+
+  /// Whether the expiry time has passed. A file without one never expires.
+  pub fn is_expired(&self) -> bool {
+    self.expiry.is_some_and(|x| x <= Utc::now())
+  }
+
+  /// The entries as RELEASES text, preceded by the manifest version and, if
+  /// the file has an expiry, the `# expires:` comment. Use this rather than
+  /// `to_string` when the file will be served to clients.
+  pub fn to_string_with_header(&self) -> String {
+    let mut ret = format!("# {} {}\n", MANIFEST_VERSION_DIRECTIVE, MANIFEST_VERSION);
+    if let Some(expiry) = self.expiry {
+      ret.push_str(&format!("# {} {}\n", EXPIRES_DIRECTIVE, expiry.to_rfc3339_opts(SecondsFormat::AutoSi, true)));
+    }
+
+    ret.push_str(&self.to_string());
+    ret
+  }
+
+  /// Parses a manifest produced by `sign_manifest`, refusing it unless the
+  /// signature matches `pub_key`.
+  #[cfg(feature = "sign")]
+  pub fn from_signed_str(content: &str, pub_key: &VerifyingKey) -> Result<ReleaseFile, ReleaseEntryError> {
+    let body = verify_manifest(pub_key, content)?;
+    ReleaseFile::parse(body)
+  }
+
+  /// Checks the signature of every signed entry, returning the ones that
+  /// fail. Unsigned entries are skipped.
+  #[cfg(feature = "sign")]
+  pub fn verify_all_signatures(&self, pub_key: &VerifyingKey) -> Vec<(&ReleaseEntry, SignatureError)> {
+    self.entries.iter()
+      .filter(|x| x.signature.is_some())
+      .filter_map(|x| x.verify_entry_signature(pub_key).err().map(|err| (x, err)))
+      .collect()
+  }
+
+  /// Downloads and parses the RELEASES file at `url`. Pass the ETag from the
+  /// last fetch to get `(None, None)` back if the file hasn't changed, so
+  /// that polling doesn't re-download it every time.
+  #[cfg(feature = "fetch")]
+  pub fn from_url_with_etag(url: &str, etag: Option<&str>, timeout: Duration) -> Result<(Option<ReleaseFile>, Option<String>), FetchError> {
+    match fetch::get_manifest(url, etag, timeout)? {
+      Some((content, etag)) => Ok((Some(ReleaseFile::parse(&content)?), etag)),
+      None => Ok((None, None))
+    }
+  }
+
+  pub fn entries(&self) -> &[ReleaseEntry] {
+    &self.entries
+  }
+
+  /// Iterates over the entries mutably, e.g. to adjust rollout percentages in place.
+  pub fn entries_mut(&mut self) -> slice::IterMut<'_, ReleaseEntry> {
+    self.entries.iter_mut()
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  /// The number of full packages, counting staging ones. Zero after
+  /// filtering for a platform usually means a publishing mistake.
+  pub fn count_full(&self) -> usize {
+    self.entries.iter().filter(|x| !x.is_delta()).count()
+  }
+
+  pub fn count_delta(&self) -> usize {
+    self.entries.iter().filter(|x| x.is_delta()).count()
+  }
+
+  pub fn get(&self, index: usize) -> Option<&ReleaseEntry> {
+    self.entries.get(index)
+  }
+
+  pub fn get_mut(&mut self, index: usize) -> Option<&mut ReleaseEntry> {
+    self.entries.get_mut(index)
+  }
+
+  pub fn get_by_sha256(&self, sha256: &Sha256Hash) -> Option<&ReleaseEntry> {
+    self.entries.iter().find(|x| x.sha256 == *sha256)
+  }
+
+  /// The newest full package that isn't a pre-release, so that most users
+  /// stay on stable while betas are published alongside.
+  pub fn latest_stable_entry(&self) -> Option<&ReleaseEntry> {
+    self.entries.iter()
+      .filter(|x| x.package_type == PackageType::Full && x.version.pre.is_empty())
+      .max_by(|a, b| a.version.cmp(&b.version))
+  }
+
+  /// Appends `entry`, refusing to add a second package with the same hash or
+  /// the same name and version as one already in the file.
+  pub fn add_entry(&mut self, entry: ReleaseEntry) -> Result<(), DuplicateError> {
+    if self.entries.iter().any(|x| x.sha256 == entry.sha256) {
+      return Err(DuplicateError::SameSha256);
+    }
+
+    if self.entries.iter().any(|x| x.source == entry.source && x.version_core() == entry.version_core()) {
+      return Err(DuplicateError::SameNameAndVersion);
+    }
+
+    self.entries.push(entry);
+    Ok(())
+  }
+
+  /// Every version in ascending order, with all of its full and delta
+  /// entries. Build metadata is ignored, and left out of the keys.
+  pub fn group_by_version(&self) -> BTreeMap<Version, Vec<&ReleaseEntry>> {
+    let mut ret = BTreeMap::new();
+    for entry in &self.entries {
+      ret.entry(entry.version_core()).or_insert_with(Vec::new).push(entry);
+    }
+
+    ret
+  }
+
+  /// The cheapest way to update from the installed version `from` to the
+  /// newest version in the file: a chain of deltas if one reaches it and is
+  /// smaller than the full package, otherwise the full package. Staging
+  /// packages and packages at 0% are skipped. Returns `None` if `from` is
+  /// already the newest.
+  pub fn best_update_plan(&self, from: &Version) -> Option<UpdatePlan> {
+    update_plan::best_plan(&self.entries, from).1
+  }
+
+  /// Like `best_update_plan`, but only considers the entries the machine
+  /// identified by `seed` is eligible for, as `ReleaseEntry::check_update`
+  /// does.
+  pub fn best_update_plan_for(&self, from: &Version, seed: &str) -> Option<UpdatePlan> {
+    update_plan::best_plan(self.entries.iter().filter(|x| x.is_eligible(seed)), from).1
+  }
+
+  /// The deltas that take the installed version `from` to `to`, one per
+  /// version in between, or `None` if a step is missing or `from` isn't in
+  /// the file. Staging packages are skipped.
+  pub fn best_delta_chain(&self, from: &Version, to: &Version) -> Option<DeltaChain> {
+    let entries = self.entries.iter().filter(|x| x.package_type != PackageType::Staging).collect::<Vec<_>>();
+    let steps = update_plan::delta_steps(&entries, from, to)?;
+
+    let chain = DeltaChain {
+      steps: steps.into_iter().cloned().collect(),
+      from_version: from.clone(),
+      to_version: to.clone(),
+    };
+    chain.validate().ok().map(|_| chain)
+  }
+
+  /// Splits the file into `(full_packages, delta_packages)`, e.g. to publish
+  /// them to different CDNs. Staging packages count as full ones.
+  pub fn split_by_type(&self) -> (ReleaseFile, ReleaseFile) {
+    let (deltas, fulls) = self.entries.iter().cloned().partition(|x| x.is_delta());
+    let with_entries = |entries| ReleaseFile { entries, expiry: self.expiry, format_version: self.format_version };
+    (with_entries(fulls), with_entries(deltas))
+  }
+
+  /// Drops entries with the same SHA256, source and version as an earlier
+  /// one, returning how many were removed. Safe to call after any `extend`.
+  pub fn deduplicate(&mut self) -> usize {
+    let before = self.entries.len();
+    let mut kept: Vec<ReleaseEntry> = Vec::with_capacity(before);
+    for entry in self.entries.drain(..) {
+      if !kept.iter().any(|x| x.sha256 == entry.sha256 && x.source == entry.source && x.version == entry.version) {
+        kept.push(entry);
+      }
+    }
+
+    self.entries = kept;
+    before - self.entries.len()
+  }
+
+  /// Sets the rollout percentage of every entry for `version`, e.g. to step
+  /// a staged rollout from 5% to 25%. Returns how many entries changed.
+  /// Signatures cover the percentage, so any entry whose percentage changes
+  /// loses its signature and has to be signed again. The same goes for
+  /// `full_rollout` and the emergency stops.
+  pub fn set_percentage_for_version(&mut self, version: &Version, percentage: i32) -> Result<usize, ReleaseEntryError> {
+    if !(0..=100).contains(&percentage) {
+      return Err(ReleaseEntryError::InvalidPercentage { value: percentage.to_string() });
+    }
+
+    Ok(self.update_percentage(version, percentage))
+  }
+
+  /// Rolls `version` out to everyone, returning how many entries changed
+  pub fn full_rollout(&mut self, version: &Version) -> usize {
+    self.update_percentage(version, 100)
+  }
+
+  /// Pulls a bad release by setting `version` to 0%, so that no client picks
+  /// it up. Returns how many entries changed.
+  pub fn emergency_stop(&mut self, version: &Version) -> usize {
+    self.update_percentage(version, 0)
+  }
+
+  /// Sets every entry in the file to 0%
+  pub fn emergency_stop_all(&mut self) {
+    for entry in &mut self.entries {
+      set_percentage(entry, 0);
+    }
+  }
+
+  fn update_percentage(&mut self, version: &Version, percentage: i32) -> usize {
+    let mut count = 0;
+    for entry in self.entries.iter_mut().filter(|x| x.version == *version) {
+      set_percentage(entry, percentage);
+      count += 1;
+    }
+
+    count
+  }
+
+  /// Keeps only the entries belonging to the `n` newest versions, e.g. to
+  /// prune old releases from the manifest after publishing a new one.
+  pub fn retain_latest_n_versions(&mut self, n: usize) {
+    let mut versions = self.entries.iter().map(|x| x.version_core()).collect::<Vec<_>>();
+    versions.sort();
+    versions.dedup();
+
+    let first_kept = versions.len().saturating_sub(n);
+    match versions.get(first_kept) {
+      Some(oldest) => self.entries.retain(|x| x.version_core() >= *oldest),
+      None => self.entries.clear()
+    }
+  }
+}
+
+impl fmt::Display for ReleaseFile {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for entry in &self.entries {
+      writeln!(f, "{}", entry)?;
+    }
+
+    Ok(())
+  }
+}
+
+impl Index<usize> for ReleaseFile {
+  type Output = ReleaseEntry;
+
+  fn index(&self, index: usize) -> &ReleaseEntry {
+    &self.entries[index]
+  }
+}
+
+impl Extend<ReleaseEntry> for ReleaseFile {
+  fn extend<T: IntoIterator<Item = ReleaseEntry>>(&mut self, iter: T) {
+    self.entries.extend(iter);
+  }
+}
+
+impl FromIterator<ReleaseEntry> for ReleaseFile {
+  fn from_iter<T: IntoIterator<Item = ReleaseEntry>>(iter: T) -> ReleaseFile {
+    ReleaseFile::from(iter.into_iter().collect::<Vec<_>>())
+  }
+}
+
+impl From<Vec<ReleaseEntry>> for ReleaseFile {
+  fn from(entries: Vec<ReleaseEntry>) -> ReleaseFile {
+    ReleaseFile { entries, ..ReleaseFile::default() }
+  }
+}
+
+impl From<ReleaseFile> for Vec<ReleaseEntry> {
+  fn from(file: ReleaseFile) -> Vec<ReleaseEntry> {
+    file.entries
+  }
+}
+
+impl IntoIterator for ReleaseFile {
+  type Item = ReleaseEntry;
+  type IntoIter = vec::IntoIter<ReleaseEntry>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.entries.into_iter()
+  }
+}
+
+impl<'a> IntoIterator for &'a ReleaseFile {
+  type Item = &'a ReleaseEntry;
+  type IntoIter = slice::Iter<'a, ReleaseEntry>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.entries.iter()
+  }
+}
+
+/// Sets the percentage of `entry`, dropping its signature if that changes
+/// it, since the signature no longer covers the line
+fn set_percentage(entry: &mut ReleaseEntry, percentage: i32) {
+  if entry.percentage != percentage {
+    entry.percentage = percentage;
+    entry.signature = None;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{Duration, TimeZone, Utc};
+  use semver::Version;
+  use super::ReleaseFile;
+  use crate::error::{DuplicateError, ReleaseEntryError};
+  use crate::release_entry::ReleaseEntry;
+  use crate::sha256_hash::Sha256Hash;
+  use crate::update_plan::PlanType;
+  use std::fs;
+
+  const FIXTURE: &str = "
+e4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-1.0.0.7z 1.0.0 12345 full
+a4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-1.1.0.7z 1.1.0 12345 full
+b4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-1.1.0-delta.7z 1.1.0 555 delta
+c4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-2.0.0-beta.1.7z 2.0.0-beta.1 34567 full
+d4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-2.0.0.7z 2.0.0 34567 full";
+
+  #[test]
+  fn set_percentage_for_version_updates_matching_entries() {
+    let mut file = ReleaseFile::parse(FIXTURE).unwrap();
+    let version = Version::parse("1.1.0").unwrap();
+
+    assert_eq!(file.set_percentage_for_version(&version, 25).unwrap(), 2);
+    let percentages = file.entries().iter().map(|x| x.percentage).collect::<Vec<_>>();
+    assert_eq!(percentages, vec![100, 25, 25, 100, 100]);
+
+    assert_eq!(file.set_percentage_for_version(&Version::parse("3.0.0").unwrap(), 25).unwrap(), 0);
+    match file.set_percentage_for_version(&version, 101) {
+      Err(ReleaseEntryError::InvalidPercentage { .. }) => (),
+      x => panic!("Expected InvalidPercentage, got {:?}", x)
+    }
+  }
+
+  #[test]
+  fn full_rollout_sets_version_to_100() {
+    let mut file = ReleaseFile::parse(FIXTURE).unwrap();
+    for entry in file.entries_mut() {
+      entry.percentage = 5;
+    }
+
+    assert_eq!(file.full_rollout(&Version::parse("2.0.0").unwrap()), 1);
+    let percentages = file.entries().iter().map(|x| x.percentage).collect::<Vec<_>>();
+    assert_eq!(percentages, vec![5, 5, 5, 5, 100]);
+  }
+
+  #[test]
+  fn emergency_stop_pulls_version() {
+    let mut file = ReleaseFile::parse(FIXTURE).unwrap();
+
+    assert_eq!(file.emergency_stop(&Version::parse("1.1.0").unwrap()), 2);
+    let percentages = file.entries().iter().map(|x| x.percentage).collect::<Vec<_>>();
+    assert_eq!(percentages, vec![100, 0, 0, 100, 100]);
+
+    file.emergency_stop_all();
+    assert!(file.entries().iter().all(|x| x.percentage == 0));
+  }
+
+  #[test]
+  fn changing_percentage_drops_stale_signatures() {
+    let mut file = ReleaseFile::parse(FIXTURE).unwrap();
+    for entry in file.entries_mut() {
+      entry.signature = Some(vec![1, 2, 3]);
+    }
+
+    file.full_rollout(&Version::parse("1.0.0").unwrap());
+    file.set_percentage_for_version(&Version::parse("1.1.0").unwrap(), 25).unwrap();
+    file.emergency_stop(&Version::parse("2.0.0").unwrap());
+    let signed = file.entries().iter().map(|x| x.signature.is_some()).collect::<Vec<_>>();
+    assert_eq!(signed, vec![true, false, false, true, false]);
+
+    file.emergency_stop_all();
+    assert!(file.entries().iter().all(|x| x.signature.is_none()));
+  }
+
+  #[test]
+  fn from_file_strips_byte_order_mark() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("RELEASES");
+    fs::write(&path, format!("\u{feff}{}", FIXTURE.trim_start())).unwrap();
+
+    let file = ReleaseFile::from_file(&path).unwrap();
+    assert_eq!(file.entries(), ReleaseFile::parse(FIXTURE).unwrap().entries());
+  }
+
+  #[test]
+  fn from_file_names_path_in_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("RELEASES");
+    fs::write(&path, format!("{}\nbad line", FIXTURE)).unwrap();
+
+    let err = ReleaseFile::from_file(&path).unwrap_err();
+    assert!(err.to_string().starts_with(&format!("failed to parse {} at line 7: ", path.display())));
+
+    match ReleaseFile::from_file(&dir.path().join("missing")) {
+      Err(ReleaseEntryError::InFile { ref error, .. }) => match **error {
+        ReleaseEntryError::Io(_) => (),
+        ref x => panic!("Expected Io, got {:?}", x)
+      },
+      x => panic!("Expected InFile, got {:?}", x)
+    }
+  }
+
+  #[test]
+  fn expiry_is_read_from_comment() {
+    let file = ReleaseFile::parse(&format!("# expires: 2025-06-01T00:00:00Z\n{}", FIXTURE)).unwrap();
+    assert_eq!(file.expiry(), Some(Utc.with_ymd_and_hms(2025, 6, 1, 0, 0, 0).unwrap()));
+    assert!(file.is_expired());
+    assert_eq!(file.len(), 5);
+
+    let file = ReleaseFile::from_reader(format!("# expires: 2025-06-01T02:00:00+02:00\n{}", FIXTURE).as_bytes()).unwrap();
+    assert_eq!(file.expiry(), Some(Utc.with_ymd_and_hms(2025, 6, 1, 0, 0, 0).unwrap()));
+
+    let file = ReleaseFile::parse(FIXTURE).unwrap();
+    assert_eq!(file.expiry(), None);
+    assert!(!file.is_expired());
+  }
+
+  #[test]
+  fn future_expiry_is_not_expired() {
+    let mut file = ReleaseFile::parse(FIXTURE).unwrap();
+    file.set_expiry(Some(Utc::now() + Duration::hours(1)));
+    assert!(!file.is_expired());
+  }
+
+  #[test]
+  fn invalid_expiry_is_an_error() {
+    let input = format!("{}\n# expires: next tuesday", FIXTURE);
+    let errors = [ReleaseFile::parse(&input).unwrap_err(), ReleaseFile::from_reader(input.as_bytes()).unwrap_err()];
+    for err in &errors {
+      match *err {
+        ReleaseEntryError::AtLine { line: 7, ref error } => match **error {
+          ReleaseEntryError::InvalidExpiry(_) => (),
+          ref x => panic!("Expected InvalidExpiry, got {:?}", x)
+        },
+        ref x => panic!("Expected AtLine, got {:?}", x)
+      }
+    }
+  }
+
+  #[test]
+  fn to_string_with_header_emits_expiry() {
+    let mut file = ReleaseFile::parse(FIXTURE).unwrap();
+    assert_eq!(file.to_string_with_header(), format!("# squirrel-manifest-version: 1\n{}", file));
+
+    file.set_expiry(Some(Utc.with_ymd_and_hms(2025, 6, 1, 0, 0, 0).unwrap()));
+    let text = file.to_string_with_header();
+    assert!(text.starts_with("# squirrel-manifest-version: 1\n# expires: 2025-06-01T00:00:00Z\n"));
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("RELEASES");
+    file.write_to_file(&path).unwrap();
+    assert_eq!(ReleaseFile::from_file(&path).unwrap().expiry(), file.expiry());
+  }
+
+  #[test]
+  fn format_version_is_read_from_comment() {
+    let file = ReleaseFile::parse(&format!("# squirrel-manifest-version: 1\n{}", FIXTURE)).unwrap();
+    assert_eq!(file.format_version(), Some(1));
+    assert_eq!(ReleaseFile::parse(FIXTURE).unwrap().format_version(), None);
+
+    let file = ReleaseFile::from_reader(format!("# squirrel-manifest-version: 2\n{}", FIXTURE).as_bytes()).unwrap();
+    assert_eq!(file.format_version(), Some(2));
+    assert_eq!(file.len(), 5);
+
+    match ReleaseFile::parse("# squirrel-manifest-version: two") {
+      Err(ReleaseEntryError::AtLine { line: 1, ref error }) => match **error {
+        ReleaseEntryError::UnknownDirective { .. } => (),
+        ref x => panic!("Expected UnknownDirective, got {:?}", x)
+      },
+      x => panic!("Expected AtLine, got {:?}", x)
+    }
+  }
+
+  #[test]
+  fn format_version_is_read_from_header_too() {
+    let file = ReleaseFile::parse(&format!("#!RELEASES v2\n{}", FIXTURE)).unwrap();
+    assert_eq!(file.format_version(), Some(2));
+    assert_eq!(file.len(), 5);
+
+    let file = ReleaseFile::from_reader(format!("#!RELEASES v1\n# squirrel-manifest-version: 2\n{}", FIXTURE).as_bytes()).unwrap();
+    assert_eq!(file.format_version(), Some(2));
+    let (header, _) = ReleaseEntry::parse_entries_with_header(&format!("#!RELEASES v1\n{}", FIXTURE)).unwrap();
+    assert_eq!(header.map(|x| x.format_version), ReleaseFile::parse(&format!("#!RELEASES v1\n{}", FIXTURE)).unwrap().format_version());
+
+    for header in &["#!RELEASES v", "#!RELEASES 2"] {
+      match ReleaseFile::parse(&format!("{}\n{}", header, FIXTURE)) {
+        Err(ReleaseEntryError::AtLine { line: 1, ref error }) => match **error {
+          ReleaseEntryError::UnknownDirective { ref value } if value == header => (),
+          ref x => panic!("Expected UnknownDirective for {}, got {:?}", header, x)
+        },
+        x => panic!("Expected AtLine, got {:?}", x)
+      }
+    }
+  }
+
+  #[test]
+  fn generate_from_dir_lists_packages() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("myproject-1.2.3-full.nupkg"), "This is a test").unwrap();
+    fs::write(dir.path().join("myproject-1.2.3-delta.nupkg"), "Delta").unwrap();
+    fs::write(dir.path().join("notes.txt"), "Not a package").unwrap();
+    fs::create_dir(dir.path().join("old.7z")).unwrap();
+
+    let version = Version::parse("1.2.3").unwrap();
+    let file = ReleaseFile::generate_from_dir(dir.path(), &version).unwrap();
+
+    assert_eq!(file.to_string(), "\
+18833da39fb9b7f8c917fe0220daf9cf12e6524df8fb16e39f04dbe827e2d200 myproject-1.2.3-delta.nupkg 1.2.3 5 delta
+c7be1ed902fb8dd4d48997c6452f5d7e509fbcdbe2808b16bcf4edce4c07d14e myproject-1.2.3-full.nupkg 1.2.3 14 full
+");
+    for entry in &file {
+      entry.verify_file(dir.path().join(entry.filename())).unwrap();
+    }
+  }
+
+  #[test]
+  fn best_update_plan_picks_smaller_plan() {
+    let file = ReleaseFile::parse(FIXTURE).unwrap();
+
+    let plan = file.best_update_plan(&Version::parse("1.0.0").unwrap()).unwrap();
+    assert_eq!(plan.plan_type, PlanType::Full);
+    assert_eq!(plan.entries, vec![file[4].clone()]);
+    assert_eq!(plan.total_bytes, 34567);
+
+    let file = ReleaseFile::parse(&FIXTURE.replace("2.0.0-beta.1 34567 full", "2.0.0-beta.1 100 delta")
+      .replace("myproject-2.0.0.7z 2.0.0 34567 full", "myproject-2.0.0.7z 2.0.0 34567 full\nf4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-2.0.0-delta.7z 2.0.0 200 delta")).unwrap();
+    let plan = file.best_update_plan(&Version::parse("1.1.0").unwrap()).unwrap();
+    assert_eq!(plan.plan_type, PlanType::DeltaChain);
+    assert_eq!(plan.entries, vec![file[3].clone(), file[5].clone()]);
+    assert_eq!(plan.total_bytes, 300);
+
+    assert!(file.best_update_plan(&Version::parse("2.0.0").unwrap()).is_none());
+  }
+
+  #[test]
+  fn best_update_plan_skips_stopped_and_ineligible_versions() {
+    let mut file = ReleaseFile::parse(FIXTURE).unwrap();
+    let from = Version::parse("1.1.0").unwrap();
+    file.emergency_stop(&Version::parse("2.0.0").unwrap());
+
+    let plan = file.best_update_plan(&from).unwrap();
+    assert_eq!(plan.entries, vec![file[3].clone()]);
+    assert_eq!(file.best_update_plan_for(&from, "machine"), Some(plan));
+
+    // A 1% rollout reaches hardly any machine, so almost every seed skips
+    // it, while the plan for everyone still includes it
+    file.set_percentage_for_version(&Version::parse("2.0.0-beta.1").unwrap(), 1).unwrap();
+    let seed = (0..).map(|x| format!("machine-{}", x)).find(|x| !file[3].is_eligible(x)).unwrap();
+    assert!(file.best_update_plan_for(&from, &seed).is_none());
+    assert_eq!(file.best_update_plan(&from).unwrap().entries, vec![file[3].clone()]);
+  }
+
+  #[test]
+  fn best_delta_chain_follows_versions() {
+    let file = ReleaseFile::parse(&format!("{}
+e4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-2.0.0-beta.1-delta.7z 2.0.0-beta.1 100 delta
+f4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-2.0.0-delta.7z 2.0.0 200 delta", FIXTURE)).unwrap();
+
+    let chain = file.best_delta_chain(&Version::parse("1.0.0").unwrap(), &Version::parse("2.0.0").unwrap()).unwrap();
+    assert_eq!(chain.steps, vec![file[2].clone(), file[5].clone(), file[6].clone()]);
+    assert_eq!(chain.total_bytes(), 555 + 100 + 200);
+
+    let chain = file.best_delta_chain(&Version::parse("1.1.0").unwrap(), &Version::parse("2.0.0-beta.1").unwrap()).unwrap();
+    assert_eq!(chain.step_count(), 1);
+
+    assert!(file.best_delta_chain(&Version::parse("0.9.0").unwrap(), &Version::parse("2.0.0").unwrap()).is_none());
+    assert!(file.best_delta_chain(&Version::parse("2.0.0").unwrap(), &Version::parse("2.0.0").unwrap()).is_none());
+
+    // Without deltas for the 2.0.0 releases only the first step exists
+    let file = ReleaseFile::parse(FIXTURE).unwrap();
+    assert!(file.best_delta_chain(&Version::parse("1.0.0").unwrap(), &Version::parse("1.1.0").unwrap()).is_some());
+    assert!(file.best_delta_chain(&Version::parse("1.0.0").unwrap(), &Version::parse("2.0.0").unwrap()).is_none());
+  }
+
+  #[test]
+  fn is_worth_using_delta_compares_sizes() {
+    let file = ReleaseFile::parse(FIXTURE).unwrap();
+    let full = file.best_update_plan(&Version::parse("1.0.0").unwrap()).unwrap();
+
+    let mut delta = full.clone();
+    delta.plan_type = PlanType::DeltaChain;
+    assert!(!delta.is_worth_using_delta(&full));
+
+    delta.total_bytes -= 1;
+    assert!(delta.is_worth_using_delta(&full));
+  }
+
+  #[test]
+  fn write_to_file_round_trips() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("RELEASES");
+    fs::write(&path, "stale").unwrap();
+
+    let file = ReleaseFile::parse(FIXTURE).unwrap();
+    file.write_to_file(&path).unwrap();
+
+    let written = ReleaseFile::from_file(&path).unwrap();
+    assert_eq!(written.entries(), file.entries());
+    assert_eq!(written.format_version(), Some(1));
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+  }
+
+  #[test]
+  fn write_to_file_cleans_up_when_rename_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("RELEASES");
+    fs::create_dir(&path).unwrap();
+    fs::write(path.join("keep"), "").unwrap();
+
+    assert!(ReleaseFile::parse(FIXTURE).unwrap().write_to_file(&path).is_err());
+    assert!(!path.with_extension("releases.tmp").exists());
+  }
+
+  #[test]
+  fn retain_latest_n_versions_keeps_newest() {
+    let mut file = ReleaseFile::parse(FIXTURE).unwrap();
+    file.retain_latest_n_versions(2);
+
+    let versions = file.entries().iter().map(|x| x.version.to_string()).collect::<Vec<_>>();
+    assert_eq!(versions, vec!["2.0.0-beta.1", "2.0.0"]);
+  }
+
+  #[test]
+  fn retain_latest_n_versions_keeps_every_entry_of_a_version() {
+    let mut file = ReleaseFile::parse(FIXTURE).unwrap();
+    file.retain_latest_n_versions(3);
+
+    assert_eq!(file.entries().len(), 4);
+    assert_eq!(file.entries()[0].version.to_string(), "1.1.0");
+  }
+
+  #[test]
+  fn retain_latest_n_versions_with_large_n_keeps_everything() {
+    let mut file = ReleaseFile::parse(FIXTURE).unwrap();
+    file.retain_latest_n_versions(10);
+
+    assert_eq!(file.entries().len(), 5);
+  }
+
+  #[test]
+  fn retain_zero_versions_removes_everything() {
+    let mut file = ReleaseFile::parse(FIXTURE).unwrap();
+    file.retain_latest_n_versions(0);
+
+    assert!(file.entries().is_empty());
+  }
+
+  #[test]
+  fn group_by_version_keys_leave_out_build_metadata() {
+    let file = ReleaseFile::parse("
+e4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-1.0.0.7z 1.0.0 12345 full
+a4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-1.2.3.7z 1.2.3+a 12345 full
+b4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-1.2.3-delta.7z 1.2.3+b 555 delta").unwrap();
+
+    let groups = file.group_by_version();
+    assert_eq!(groups.keys().map(|x| x.to_string()).collect::<Vec<_>>(), vec!["1.0.0", "1.2.3"]);
+    assert_eq!(groups.values().map(|x| x.len()).collect::<Vec<_>>(), vec![1, 2]);
+  }
+
+  #[test]
+  fn latest_stable_entry_skips_prereleases() {
+    let file = ReleaseFile::parse("
+a4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-1.9.9.7z 1.9.9 12345 full
+b4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-2.0.0-beta.1.7z 2.0.0-beta.1 34567 full
+c4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-1.9.9-delta.7z 1.9.9 555 delta").unwrap();
+
+    assert_eq!(file.latest_stable_entry().unwrap().version.to_string(), "1.9.9");
+    assert_eq!(ReleaseFile::parse(FIXTURE).unwrap().latest_stable_entry().unwrap().version.to_string(), "2.0.0");
+    assert!(ReleaseFile::default().latest_stable_entry().is_none());
+  }
+
+  #[test]
+  fn add_entry_appends_new_entries() {
+    let mut file = ReleaseFile::parse(FIXTURE).unwrap();
+    let entry = ReleaseEntry::parse("f4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-2.1.0.7z 2.1.0 34567 full").unwrap();
+
+    file.add_entry(entry).unwrap();
+    assert_eq!(file.entries().len(), 6);
+  }
+
+  #[test]
+  fn add_entry_rejects_same_sha256() {
+    let mut file = ReleaseFile::parse(FIXTURE).unwrap();
+    let entry = ReleaseEntry::parse("e4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-2.1.0.7z 2.1.0 34567 full").unwrap();
+
+    assert_eq!(file.add_entry(entry), Err(DuplicateError::SameSha256));
+    assert_eq!(file.entries().len(), 5);
+  }
+
+  #[test]
+  fn add_entry_rejects_same_name_and_version() {
+    let mut file = ReleaseFile::parse(FIXTURE).unwrap();
+    let entry = ReleaseEntry::parse("f4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-2.0.0.7z 2.0.0 11111 full").unwrap();
+
+    assert_eq!(file.add_entry(entry), Err(DuplicateError::SameNameAndVersion));
+    assert_eq!(file.entries().len(), 5);
+  }
+
+  #[test]
+  fn extend_appends_entries() {
+    let mut file = ReleaseFile::parse(FIXTURE).unwrap();
+    let other = ReleaseFile::parse(FIXTURE).unwrap();
+
+    file.extend(other);
+    assert_eq!(file.entries().len(), 10);
+  }
+
+  #[test]
+  fn group_by_version_sorts_versions() {
+    let file = ReleaseFile::parse(FIXTURE).unwrap();
+    let groups = file.group_by_version();
+
+    let versions = groups.keys().map(|x| x.to_string()).collect::<Vec<_>>();
+    assert_eq!(versions, vec!["1.0.0", "1.1.0", "2.0.0-beta.1", "2.0.0"]);
+    assert_eq!(groups.values().nth(1).unwrap(), &vec![&file[1], &file[2]]);
+  }
+
+  #[test]
+  fn split_by_type_separates_deltas() {
+    let file = ReleaseFile::parse(FIXTURE).unwrap();
+    let (fulls, deltas) = file.split_by_type();
+
+    assert_eq!(fulls.len(), 4);
+    assert!(fulls.entries().iter().all(|x| !x.is_delta()));
+    assert_eq!(deltas.len(), 1);
+    assert_eq!(deltas[0].length, Some(555));
+  }
+
+  #[test]
+  fn deduplicate_keeps_first_occurrence() {
+    let entry = ReleaseEntry::parse("f4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-2.1.0.7z 2.1.0 34567 full").unwrap();
+    let mut file = vec![entry.clone(), entry.clone(), entry.clone()].into_iter().collect::<ReleaseFile>();
+
+    assert_eq!(file.deduplicate(), 2);
+    assert_eq!(file.entries(), &[entry]);
+    assert_eq!(file.deduplicate(), 0);
+  }
+
+  #[test]
+  fn release_files_compose_with_iterators() {
+    let file = ReleaseFile::parse(FIXTURE).unwrap();
+    assert_eq!((&file).into_iter().filter(|x| x.is_delta()).count(), 1);
+
+    let fulls = file.into_iter().filter(|x| !x.is_delta()).collect::<ReleaseFile>();
+    assert_eq!(fulls.entries().len(), 4);
+  }
+
+  #[test]
+  fn converts_to_and_from_vec() {
+    let entries = ReleaseEntry::parse_entries(FIXTURE).unwrap();
+    let file = ReleaseFile::from(entries.clone());
+    assert_eq!(file.entries(), &entries[..]);
+
+    let back: Vec<ReleaseEntry> = file.into();
+    assert_eq!(back, entries);
+  }
+
+  #[test]
+  fn accessors_reflect_entries() {
+    let mut file = ReleaseFile::parse(FIXTURE).unwrap();
+
+    assert_eq!(file.len(), 5);
+    assert!(!file.is_empty());
+    assert!(ReleaseFile::default().is_empty());
+
+    assert_eq!(file.count_full(), 4);
+    assert_eq!(file.count_delta(), 1);
+    assert_eq!(ReleaseFile::default().count_full(), 0);
+
+    assert_eq!(file.get(2).unwrap().length, Some(555));
+    assert!(file.get(5).is_none());
+    assert_eq!(file[4].version.to_string(), "2.0.0");
+
+    file.get_mut(0).unwrap().percentage = 10;
+    assert_eq!(file[0].percentage, 10);
+  }
+
+  #[test]
+  fn get_by_sha256_finds_matching_entry() {
+    let file = ReleaseFile::parse(FIXTURE).unwrap();
+    let sha = file[3].sha256;
+
+    assert_eq!(file.get_by_sha256(&sha).unwrap().version.to_string(), "2.0.0-beta.1");
+    assert!(file.get_by_sha256(&Sha256Hash::default()).is_none());
+  }
+
+  #[test]
+  #[should_panic]
+  fn index_out_of_bounds_panics() {
+    let file = ReleaseFile::parse(FIXTURE).unwrap();
+    let _ = &file[5];
+  }
+
+  #[test]
+  fn entries_mut_updates_in_place() {
+    let mut file = ReleaseFile::parse(FIXTURE).unwrap();
+    for entry in file.entries_mut() {
+      entry.percentage = 25;
+    }
+
+    assert!(file.entries().iter().all(|x| x.percentage == 25));
+  }
+
+  #[cfg(feature = "sign")]
+  #[test]
+  fn from_signed_str_verifies_before_parsing() {
+    use crate::sign::{sign_manifest, SigningKey};
+
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let signed = sign_manifest(&key, FIXTURE);
+
+    let file = ReleaseFile::from_signed_str(&signed, &key.verifying_key()).unwrap();
+    assert_eq!(file.len(), 5);
+
+    match ReleaseFile::from_signed_str(FIXTURE, &key.verifying_key()) {
+      Err(ReleaseEntryError::InvalidSignature(_)) => (),
+      x => panic!("Expected InvalidSignature, got {:?}", x)
+    }
+  }
+
+  #[cfg(feature = "sign")]
+  #[test]
+  fn verify_all_signatures_reports_failures() {
+    use crate::sign::SigningKey;
+
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let mut file = ReleaseFile::parse(FIXTURE).unwrap();
+    for entry in file.entries_mut().take(2) {
+      entry.sign(&key);
+    }
+    file.get_mut(1).unwrap().percentage = 50;
+
+    let failures = file.verify_all_signatures(&key.verifying_key());
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].0.source.to_string(), "myproject-1.1.0.7z");
+  }
+
+  #[cfg(feature = "fetch")]
+  #[test]
+  fn from_url_with_etag_downloads_manifest() {
+    use crate::fetch::tests::{ok_response, serve_once};
+    use std::time::Duration;
+
+    let (url, server) = serve_once(ok_response(FIXTURE, "\"v1\""));
+    let (file, etag) = ReleaseFile::from_url_with_etag(&url, None, Duration::from_secs(5)).unwrap();
+
+    assert_eq!(file.unwrap().len(), 5);
+    assert_eq!(etag.as_deref(), Some("\"v1\""));
+    assert!(!server.join().unwrap().to_lowercase().contains("if-none-match"));
+  }
+
+  #[cfg(feature = "fetch")]
+  #[test]
+  fn from_url_with_etag_handles_not_modified() {
+    use crate::fetch::tests::{not_modified_response, serve_once};
+    use std::time::Duration;
+
+    let (url, server) = serve_once(not_modified_response());
+    let (file, etag) = ReleaseFile::from_url_with_etag(&url, Some("\"v1\""), Duration::from_secs(5)).unwrap();
+
+    assert!(file.is_none());
+    assert!(etag.is_none());
+    assert!(server.join().unwrap().to_lowercase().contains("if-none-match: \"v1\""));
+  }
+
+  #[cfg(feature = "fetch")]
+  #[test]
+  fn from_url_with_etag_reports_errors() {
+    use crate::fetch::tests::serve_once;
+    use crate::fetch::FetchError;
+    use std::time::Duration;
+
+    let (url, server) = serve_once("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned());
+    match ReleaseFile::from_url_with_etag(&url, None, Duration::from_secs(5)) {
+      Err(FetchError::UnexpectedStatus(404)) => (),
+      x => panic!("Expected UnexpectedStatus, got {:?}", x)
+    }
+    server.join().unwrap();
+  }
+}
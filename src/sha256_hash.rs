@@ -0,0 +1,123 @@
+use crate::crypto::sha256_of_reader;
+use crate::error::ReleaseEntryError;
+use hex::ToHex;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The size of a SHA256 hash in bytes
+pub const SHA256_BYTES: usize = 32;
+/// The length of a SHA256 hash as a hex string in a RELEASES file
+pub const SHA256_HEX_LEN: usize = SHA256_BYTES * 2;
+
+/// A SHA256 digest, shown as lowercase hex
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Sha256Hash([u8; SHA256_BYTES]);
+
+impl Sha256Hash {
+  /// Decodes a 64 character hex string, validating it the same way the
+  /// RELEASES parser does.
+  pub fn from_hex(s: &str) -> Result<Sha256Hash, ReleaseEntryError> {
+    // Count chars rather than bytes so that a pasted non-breaking space is
+    // reported as a bad character rather than a bad length
+    if s.chars().count() != SHA256_HEX_LEN {
+      return Err(ReleaseEntryError::MalformedSha256 { reason: "wrong length" });
+    }
+
+    // Decode straight into the array, this runs for every RELEASES line. A
+    // non-ASCII char makes the byte length wrong, so pairs can't line up.
+    let non_hex = || ReleaseEntryError::MalformedSha256 { reason: "non-hex character" };
+    if s.len() != SHA256_HEX_LEN {
+      return Err(non_hex());
+    }
+
+    let mut sha = [0; SHA256_BYTES];
+    for (byte, pair) in sha.iter_mut().zip(s.as_bytes().chunks(2)) {
+      *byte = hex_value(pair[0]).ok_or_else(non_hex)? << 4 | hex_value(pair[1]).ok_or_else(non_hex)?;
+    }
+
+    Ok(Sha256Hash(sha))
+  }
+
+  /// Hashes the file at `path`
+  pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Sha256Hash, io::Error> {
+    sha256_of_reader(File::open(path)?)
+  }
+
+  pub fn as_bytes(&self) -> &[u8; SHA256_BYTES] {
+    &self.0
+  }
+}
+
+fn hex_value(c: u8) -> Option<u8> {
+  match c {
+    b'0'..=b'9' => Some(c - b'0'),
+    b'a'..=b'f' => Some(c - b'a' + 10),
+    b'A'..=b'F' => Some(c - b'A' + 10),
+    _ => None
+  }
+}
+
+impl From<[u8; SHA256_BYTES]> for Sha256Hash {
+  fn from(bytes: [u8; SHA256_BYTES]) -> Sha256Hash {
+    Sha256Hash(bytes)
+  }
+}
+
+impl AsRef<[u8]> for Sha256Hash {
+  fn as_ref(&self) -> &[u8] {
+    &self.0
+  }
+}
+
+impl fmt::Display for Sha256Hash {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.0.to_hex())
+  }
+}
+
+impl FromStr for Sha256Hash {
+  type Err = ReleaseEntryError;
+
+  fn from_str(s: &str) -> Result<Sha256Hash, ReleaseEntryError> {
+    Sha256Hash::from_hex(s)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use hex::FromHex;
+  use std::fs;
+  use super::Sha256Hash;
+
+  #[test]
+  fn hex_round_trips() {
+    let hex = "e4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35";
+    let sha = hex.parse::<Sha256Hash>().unwrap();
+
+    assert_eq!(sha.as_bytes()[0], 0xE4);
+    assert_eq!(sha.as_bytes()[31], 0x35);
+    assert_eq!(sha.to_string(), hex);
+    assert_eq!(sha, Sha256Hash::from(*sha.as_bytes()));
+  }
+
+  #[test]
+  fn from_hex_matches_hex_crate() {
+    for hex in &["e4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35", "E4548FBA3F902E63E3FFF36DB7CBBD1837493E21C51F0751E51EE1483DDD0F35"] {
+      let expected = Vec::from_hex(hex).unwrap();
+      assert_eq!(&Sha256Hash::from_hex(hex).unwrap().as_bytes()[..], &expected[..]);
+    }
+  }
+
+  #[test]
+  fn from_file_hashes_contents() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("myproject.7z");
+    fs::write(&path, "This is a test").unwrap();
+
+    let sha = Sha256Hash::from_file(&path).unwrap();
+    assert_eq!(sha.to_string(), "c7be1ed902fb8dd4d48997c6452f5d7e509fbcdbe2808b16bcf4edce4c07d14e");
+  }
+}
@@ -0,0 +1,130 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, Verifier};
+use std::error::{Error};
+use std::fmt;
+
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+const SIGNATURE_PREFIX: &str = "# sig: ";
+
+/// Everything that can go wrong while checking a signed manifest
+#[derive(Debug)]
+pub enum SignatureError {
+  MissingSignature,
+  MalformedSignature,
+  BadSignature(ed25519_dalek::SignatureError),
+}
+
+impl fmt::Display for SignatureError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      SignatureError::MissingSignature => write!(f, "no signature was found"),
+      SignatureError::MalformedSignature => write!(f, "signature is not a base64 Ed25519 signature"),
+      SignatureError::BadSignature(ref err) => write!(f, "signature does not match: {}", err),
+    }
+  }
+}
+
+impl Error for SignatureError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match *self {
+      SignatureError::BadSignature(ref err) => Some(err),
+      _ => None
+    }
+  }
+}
+
+/// Signs `message`, returning the raw 64 byte signature
+pub(crate) fn sign_raw(key: &SigningKey, message: &[u8]) -> Vec<u8> {
+  key.sign(message).to_bytes().to_vec()
+}
+
+/// Checks a raw signature produced by `sign_raw`
+pub(crate) fn verify_raw(pub_key: &VerifyingKey, message: &[u8], signature: &[u8]) -> Result<(), SignatureError> {
+  let signature = Signature::from_slice(signature).map_err(|_| SignatureError::MalformedSignature)?;
+  pub_key.verify(message, &signature).map_err(SignatureError::BadSignature)
+}
+
+/// Signs `message`, returning the base64 encoded signature
+fn sign_bytes(key: &SigningKey, message: &[u8]) -> String {
+  STANDARD.encode(sign_raw(key, message))
+}
+
+/// Checks a base64 encoded signature produced by `sign_bytes`
+fn verify_bytes(pub_key: &VerifyingKey, message: &[u8], signature: &str) -> Result<(), SignatureError> {
+  let bytes = STANDARD.decode(signature).map_err(|_| SignatureError::MalformedSignature)?;
+  verify_raw(pub_key, message, &bytes)
+}
+
+/// Signs `manifest` with `key`, returning it with a `# sig: <base64>` line
+/// prepended. The signature line is a comment, so clients that don't check
+/// signatures can still parse the result.
+pub fn sign_manifest(key: &SigningKey, manifest: &str) -> String {
+  format!("{}{}\n{}", SIGNATURE_PREFIX, sign_bytes(key, manifest.as_bytes()), manifest)
+}
+
+/// Checks the `# sig:` line of a manifest produced by `sign_manifest` and
+/// returns the signed body without it.
+pub fn verify_manifest<'a>(pub_key: &VerifyingKey, manifest: &'a str) -> Result<&'a str, SignatureError> {
+  let (first_line, body) = match manifest.find('\n') {
+    Some(idx) => (&manifest[..idx], &manifest[idx + 1..]),
+    None => (manifest, "")
+  };
+
+  if !first_line.starts_with(SIGNATURE_PREFIX) {
+    return Err(SignatureError::MissingSignature);
+  }
+
+  verify_bytes(pub_key, body.as_bytes(), first_line[SIGNATURE_PREFIX.len()..].trim())?;
+  Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{sign_manifest, verify_manifest, SignatureError, SigningKey};
+
+  const FIXTURE: &str = "
+e4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject.7z 1.2.3 12345 full
+a4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 myproject-delta.7z 1.2.3 555 delta
+";
+
+  #[test]
+  fn signed_manifest_should_verify() {
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let signed = sign_manifest(&key, FIXTURE);
+
+    assert!(signed.starts_with("# sig: "));
+    assert_eq!(verify_manifest(&key.verifying_key(), &signed).unwrap(), FIXTURE);
+  }
+
+  #[test]
+  fn tampered_manifest_should_fail() {
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let signed = sign_manifest(&key, FIXTURE).replace("12345", "12346");
+
+    match verify_manifest(&key.verifying_key(), &signed) {
+      Err(SignatureError::BadSignature(_)) => (),
+      x => panic!("Expected BadSignature, got {:?}", x)
+    }
+  }
+
+  #[test]
+  fn manifest_signed_with_another_key_should_fail() {
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let other = SigningKey::from_bytes(&[8; 32]);
+    let signed = sign_manifest(&other, FIXTURE);
+
+    verify_manifest(&key.verifying_key(), &signed).unwrap_err();
+  }
+
+  #[test]
+  fn unsigned_manifest_should_fail() {
+    let key = SigningKey::from_bytes(&[7; 32]);
+
+    match verify_manifest(&key.verifying_key(), FIXTURE) {
+      Err(SignatureError::MissingSignature) => (),
+      x => panic!("Expected MissingSignature, got {:?}", x)
+    }
+  }
+}
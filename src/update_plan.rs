@@ -0,0 +1,306 @@
+#[cfg(feature = "parallel")]
+use crate::crypto::VerifyingWriter;
+#[cfg(feature = "parallel")]
+use crate::download::{DownloadError, Downloader};
+#[cfg(feature = "parallel")]
+use crate::package_source::PackageSource;
+use crate::package_type::PackageType;
+use crate::release_entry::ReleaseEntry;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use semver::Version;
+#[cfg(feature = "parallel")]
+use std::fs::{self, File};
+#[cfg(feature = "parallel")]
+use std::io;
+#[cfg(feature = "parallel")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "parallel")]
+use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+#[cfg(feature = "parallel")]
+use url::Url;
+
+/// How an `UpdatePlan` gets to the new version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PlanType {
+  /// Download the new version's full package
+  Full,
+  /// Apply one delta per version on top of the installed one
+  DeltaChain,
+}
+
+/// The packages to download and apply, in order, to update to a newer version
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdatePlan {
+  pub entries: Vec<ReleaseEntry>,
+  /// The size of everything in `entries`. Entries of unknown size count as 0.
+  pub total_bytes: u64,
+  pub plan_type: PlanType,
+}
+
+impl UpdatePlan {
+  fn new(entries: Vec<&ReleaseEntry>, plan_type: PlanType) -> UpdatePlan {
+    UpdatePlan {
+      total_bytes: entries.iter().filter_map(|x| x.length).sum(),
+      entries: entries.into_iter().cloned().collect(),
+      plan_type,
+    }
+  }
+
+  /// Whether this plan downloads less than `full_plan`
+  pub fn is_worth_using_delta(&self, full_plan: &UpdatePlan) -> bool {
+    self.total_bytes < full_plan.total_bytes
+  }
+
+  /// Downloads every entry into `dest_dir` under its `filename()`, up to
+  /// `parallelism` at a time. Each download is streamed through its own
+  /// `VerifyingWriter`, so it's checked as it's written rather than read
+  /// back afterwards, and removed if it doesn't match.
+  /// Returns one result per entry, in the same order. Relative filenames are
+  /// resolved against `base_url`, usually where the RELEASES file came from.
+  ///
+  /// `downloader` is shared by every thread in the pool, so it has to be
+  /// `Sync`. A `parallelism` of 0 fails every entry with `InvalidInput`.
+  #[cfg(feature = "parallel")]
+  pub fn download_all_parallel(&self, downloader: &(dyn Downloader + Sync), base_url: &Url, dest_dir: &Path, parallelism: usize) -> Vec<Result<PathBuf, DownloadError>> {
+    let fail_all = |kind: io::ErrorKind, message: &str| self.entries.iter().map(|_| Err(DownloadError::Io(io::Error::new(kind, message)))).collect();
+    if parallelism == 0 {
+      return fail_all(io::ErrorKind::InvalidInput, "parallelism must be at least 1");
+    }
+
+    let pool = match rayon::ThreadPoolBuilder::new().num_threads(parallelism).build() {
+      Ok(pool) => pool,
+      Err(err) => return fail_all(io::ErrorKind::Other, &err.to_string())
+    };
+
+    pool.install(|| {
+      self.entries.par_iter().map(|entry| {
+        let dest = entry.path_in(dest_dir).map_err(|x| DownloadError::Io(io::Error::new(io::ErrorKind::InvalidInput, x)))?;
+        let url = package_url(base_url, &entry.source).map_err(|x| DownloadError::Io(io::Error::new(io::ErrorKind::InvalidInput, x)))?;
+        let result = download_verified(downloader, entry, url.as_str(), &dest);
+        if result.is_err() {
+          let _ = fs::remove_file(&dest);
+        }
+
+        result.map(|_| dest)
+      }).collect()
+    })
+  }
+}
+
+/// Where to download a package from, with relative filenames resolved
+/// against `base_url` and percent-encoded the same way as in RELEASES files
+#[cfg(feature = "parallel")]
+fn package_url(base_url: &Url, source: &PackageSource) -> Result<Url, url::ParseError> {
+  match *source {
+    PackageSource::Url(ref url) => Ok(url.clone()),
+    PackageSource::Filename(ref name) => {
+      let segments = name.split('/')
+        .map(|x| utf8_percent_encode(x, PATH_SEGMENT_ENCODE_SET).to_string())
+        .collect::<Vec<_>>();
+      base_url.join(&segments.join("/"))
+    }
+  }
+}
+
+#[cfg(feature = "parallel")]
+fn download_verified(downloader: &(dyn Downloader + Sync), entry: &ReleaseEntry, url: &str, dest: &Path) -> Result<(), DownloadError> {
+  let mut scratch = dest.as_os_str().to_owned();
+  scratch.push(".part");
+
+  let mut writer = VerifyingWriter::new(File::create(dest)?);
+  downloader.download_to_writer(url, &mut writer, Path::new(&scratch), entry)?;
+  let (file, sha256, length) = writer.finalize();
+  file.sync_all()?;
+
+  if !entry.matches_written(&sha256, length) {
+    return Err(DownloadError::HashMismatch);
+  }
+
+  Ok(())
+}
+
+/// One delta for each version in `entries` after `from` up to and including
+/// `to`, in order, or `None` if any is missing. Deltas only apply on top of
+/// the version before them, so `from` itself has to be in `entries` too.
+pub(crate) fn delta_steps<'a>(entries: &[&'a ReleaseEntry], from: &Version, to: &Version) -> Option<Vec<&'a ReleaseEntry>> {
+  if !entries.iter().any(|x| x.is_same_version(from)) {
+    return None;
+  }
+
+  let mut versions = entries.iter()
+    .map(|x| x.version_core())
+    .filter(|x| x > from && x <= to)
+    .collect::<Vec<_>>();
+  versions.sort();
+  versions.dedup();
+
+  versions.iter()
+    .map(|v| entries.iter().find(|x| x.is_delta() && x.version_core() == *v).cloned())
+    .collect()
+}
+
+/// Finds the newest version in `entries` after `from`, and the smaller of
+/// the full and delta plans that reach it. Staging packages are skipped, as
+/// are packages at 0% so that an emergency stop holds whatever the caller
+/// filtered on. A delta chain is only considered if `delta_steps` finds one.
+pub(crate) fn best_plan<'a, I>(entries: I, from: &Version) -> (Option<Version>, Option<UpdatePlan>)
+  where I: IntoIterator<Item = &'a ReleaseEntry>
+{
+  let entries = entries.into_iter()
+    .filter(|x| x.package_type != PackageType::Staging && x.percentage > 0)
+    .collect::<Vec<_>>();
+
+  let target = match entries.iter().filter(|x| x.is_newer_than(from)).map(|x| x.version_core()).max() {
+    Some(v) => v,
+    None => return (None, None)
+  };
+
+  let full = entries.iter()
+    .filter(|x| x.package_type == PackageType::Full && x.version_core() == target)
+    .min_by_key(|x| x.length)
+    .map(|x| UpdatePlan::new(vec![*x], PlanType::Full));
+
+  let deltas = delta_steps(&entries, from, &target).map(|x| UpdatePlan::new(x, PlanType::DeltaChain));
+
+  let plan = match (full, deltas) {
+    (Some(full), Some(deltas)) => Some(if deltas.is_worth_using_delta(&full) { deltas } else { full }),
+    (full, deltas) => full.or(deltas)
+  };
+
+  (Some(target), plan)
+}
+
+#[cfg(all(test, feature = "parallel"))]
+mod tests {
+  use super::{package_url, PlanType, UpdatePlan};
+  use crate::crypto::sha256_of_reader;
+  use crate::download::{DownloadError, Downloader};
+  use crate::package_source::PackageSource;
+  use crate::release_entry::ReleaseEntry;
+  use std::fs;
+  use std::io::{self, Write};
+  use std::path::Path;
+  use url::Url;
+
+  const BASE_URL: &str = "https://example.com/releases/";
+
+  /// Writes the last part of each package's URL as its contents
+  struct NameDownloader;
+
+  impl Downloader for NameDownloader {
+    fn download(&self, url: &str, dest: &Path, _expected: &ReleaseEntry) -> Result<(), DownloadError> {
+      fs::write(dest, url.rsplit('/').next().unwrap())?;
+      Ok(())
+    }
+  }
+
+  /// Only streams, so a download that goes through a file would panic
+  struct StreamingDownloader;
+
+  impl Downloader for StreamingDownloader {
+    fn download(&self, _url: &str, _dest: &Path, _expected: &ReleaseEntry) -> Result<(), DownloadError> {
+      panic!("Should have streamed");
+    }
+
+    fn download_to_writer(&self, url: &str, writer: &mut dyn Write, _scratch: &Path, _expected: &ReleaseEntry) -> Result<(), DownloadError> {
+      writer.write_all(url.rsplit('/').next().unwrap().as_bytes())?;
+      Ok(())
+    }
+  }
+
+  fn base_url() -> Url {
+    Url::parse(BASE_URL).unwrap()
+  }
+
+  fn entry_named(name: &str, contents: &str) -> ReleaseEntry {
+    ReleaseEntry {
+      source: PackageSource::Filename(name.to_owned()),
+      sha256: sha256_of_reader(contents.as_bytes()).unwrap(),
+      length: Some(contents.len() as u64),
+      ..ReleaseEntry::default()
+    }
+  }
+
+  #[test]
+  fn download_all_parallel_verifies_each_entry() {
+    let dir = tempfile::tempdir().unwrap();
+    let entries = [
+      entry_named("myproject-1.1.0-delta.7z", "myproject-1.1.0-delta.7z"),
+      entry_named("myproject-1.2.0-delta.7z", "myproject-1.2.0-delta.7z"),
+      entry_named("myproject-1.3.0-delta.7z", "something else"),
+    ];
+    let plan = UpdatePlan::new(entries.iter().collect(), PlanType::DeltaChain);
+
+    let results = plan.download_all_parallel(&NameDownloader, &base_url(), dir.path(), 2);
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].as_ref().unwrap(), &dir.path().join("myproject-1.1.0-delta.7z"));
+    assert_eq!(fs::read_to_string(results[1].as_ref().unwrap()).unwrap(), "myproject-1.2.0-delta.7z");
+    match results[2] {
+      Err(DownloadError::HashMismatch) => (),
+      ref x => panic!("Expected HashMismatch, got {:?}", x)
+    }
+  }
+
+  #[test]
+  fn download_all_parallel_streams_when_it_can() {
+    let dir = tempfile::tempdir().unwrap();
+    let entries = [
+      entry_named("myproject-1.1.0-delta.7z", "myproject-1.1.0-delta.7z"),
+      entry_named("myproject-1.2.0-delta.7z", "something else"),
+    ];
+    let plan = UpdatePlan::new(entries.iter().collect(), PlanType::DeltaChain);
+
+    let results = plan.download_all_parallel(&StreamingDownloader, &base_url(), dir.path(), 2);
+    assert_eq!(fs::read_to_string(results[0].as_ref().unwrap()).unwrap(), "myproject-1.1.0-delta.7z");
+    match results[1] {
+      Err(DownloadError::HashMismatch) => (),
+      ref x => panic!("Expected HashMismatch, got {:?}", x)
+    }
+
+    // Neither the bad download nor any scratch file is left behind
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+  }
+
+  #[test]
+  fn package_url_resolves_filenames_against_base_url() {
+    let sources = [
+      PackageSource::Filename("my project-1.1.0-delta.7z".to_owned()),
+      PackageSource::Url(Url::parse("https://cdn.example.com/myproject-1.2.0-delta.7z").unwrap()),
+    ];
+    let urls = sources.iter().map(|x| package_url(&base_url(), x).unwrap().into_string()).collect::<Vec<_>>();
+    assert_eq!(urls, vec![
+      "https://example.com/releases/my%20project-1.1.0-delta.7z",
+      "https://cdn.example.com/myproject-1.2.0-delta.7z",
+    ]);
+  }
+
+  #[test]
+  fn download_all_parallel_rejects_zero_parallelism() {
+    let dir = tempfile::tempdir().unwrap();
+    let entries = [entry_named("myproject-1.1.0-delta.7z", "myproject-1.1.0-delta.7z")];
+    let plan = UpdatePlan::new(entries.iter().collect(), PlanType::DeltaChain);
+
+    match plan.download_all_parallel(&NameDownloader, &base_url(), dir.path(), 0)[..] {
+      [Err(DownloadError::Io(ref err))] if err.kind() == io::ErrorKind::InvalidInput => (),
+      ref x => panic!("Expected InvalidInput, got {:?}", x)
+    }
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+  }
+
+  #[test]
+  fn download_all_parallel_refuses_paths_outside_dest_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("pkgs");
+    fs::create_dir(&dest).unwrap();
+
+    let entries = ReleaseEntry::parse_entries("e4548fba3f902e63e3fff36db7cbbd1837493e21c51f0751e51ee1483ddd0f35 ..%2Fevil.7z 1.2.3 12345 delta").unwrap();
+    let plan = UpdatePlan::new(entries.iter().collect(), PlanType::DeltaChain);
+
+    match plan.download_all_parallel(&NameDownloader, &base_url(), &dest, 2)[0] {
+      Err(DownloadError::Io(ref err)) if err.kind() == io::ErrorKind::InvalidInput => (),
+      ref x => panic!("Expected InvalidInput, got {:?}", x)
+    }
+    assert!(!dir.path().join("evil.7z").exists());
+  }
+}